@@ -2,11 +2,9 @@ import 'dart:async';
 
 import 'package:flame/components.dart';
 import 'package:flame/effects.dart';
-import 'package:icedash/src/rust/api/direction.dart';
 
 abstract class Actor extends PositionComponent implements OpacityProvider {
   String? asset;
-  bool colision;
   bool selffade;
   SpriteComponent? display;
 
@@ -14,7 +12,6 @@ abstract class Actor extends PositionComponent implements OpacityProvider {
     this.asset, {
     super.position,
     super.angle,
-    this.colision = true,
     this.selffade = false,
   }) {
     super.priority = 10;
@@ -22,10 +19,6 @@ abstract class Actor extends PositionComponent implements OpacityProvider {
     super.anchor = Anchor.center;
   }
 
-  Future<bool> hit(Direction dir);
-
-  void predictedHit(Vector2 startOfMovement, Direction dir);
-
   @override
   FutureOr<void> onLoad() async {
     if (asset != null) {
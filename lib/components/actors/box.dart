@@ -2,16 +2,12 @@ import 'package:flame/components.dart';
 import 'package:flame/effects.dart';
 import 'package:flame/flame.dart';
 import 'package:icedash/components/actor.dart';
-import 'package:icedash/components/room.dart';
 import 'package:icedash/config.dart';
-import 'package:icedash/extensions.dart';
 import 'package:icedash/main.dart';
-import 'package:icedash/src/rust/api/direction.dart';
 
 class Box extends Actor {
-  RoomComponent room;
   late SpriteAnimationComponent boxDisplay;
-  Box(this.room, {super.position}) : super(null);
+  Box({super.position}) : super(null);
   bool moving = false;
 
   @override
@@ -29,12 +25,10 @@ class Box extends Actor {
     add(boxDisplay);
   }
 
-  @override
-  Future<bool> hit(Direction dir) async {
+  void slideTo(Vector2 destination) {
     playAudio('hit_box.mp3');
 
-    Vector2 destination = await predictHit(dir);
-    int movementLenght = (destination - position).length.floor();
+    int movementLenght = (destination - position).length.round();
 
     if (movementLenght != 0) {
       moving = true;
@@ -49,32 +43,13 @@ class Box extends Actor {
           LinearEffectController(secPerStep * movementLenght),
           onComplete: () {
             boxDisplay.playing = false;
-
-            room.hit(destination + dir.dartVector(), dir, box: true);
             moving = false;
           },
         ),
       );
-
-      return true;
-    }
-    return await room.hit(destination + dir.dartVector(), dir, box: true);
-  }
-
-  Future<Vector2> predictHit(Direction dir) async {
-    Vector2 cursor = position;
-    Vector2 delta = dir.dartVector();
-
-    while ((await room.canBoxWalkInto(cursor + delta, dir))) {
-      cursor = cursor + delta;
     }
-
-    return cursor;
   }
 
-  @override
-  void predictedHit(Vector2 startOfMovement, Direction dir) {}
-
   bool shinyTime() {
     int time = (DateTime.now().millisecondsSinceEpoch).floor() % 7000;
     // print("$time ${time < 2000}");
@@ -6,7 +6,6 @@ import 'package:flame/effects.dart';
 import 'package:icedash/components/actor.dart';
 import 'package:icedash/components/room.dart';
 import 'package:icedash/components/sign.dart';
-import 'package:icedash/config.dart';
 import 'package:icedash/game.dart';
 import 'package:icedash/src/rust/api/direction.dart';
 import 'package:icedash/src/rust/api/main.dart';
@@ -30,7 +29,6 @@ class Gate extends Actor with HasGameReference<IceDashGame> {
     super.position,
   }) : super(
          "fade.png",
-         colision: false,
          angle: switch (innerDirection) {
            Direction.west => pi / 2,
            Direction.north => pi,
@@ -49,16 +47,9 @@ class Gate extends Actor with HasGameReference<IceDashGame> {
     return await super.onLoad();
   }
 
-  @override
-  Future<bool> hit(Direction dir) async {
-    return false;
-  }
-
-  @override
-  void predictedHit(Vector2 startingPos, Direction dir) {
-    var secsToExit = secPerStep * ((startingPos - position).length);
-
-    dartWorkerHalt(millis: BigInt.from(secsToExit * 1000 + 1));
+  // The player is on their way out and gets here in `secsToExit`
+  void enter(double secsToExit, Direction dir) {
+    world.roomTraversal.haltGeneration((secsToExit * 1000 + 1).toInt());
 
     add(
       OpacityEffect.fadeOut(
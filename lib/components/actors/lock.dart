@@ -1,15 +1,5 @@
-import 'package:flame/components.dart';
 import 'package:icedash/components/actor.dart';
-import 'package:icedash/src/rust/api/direction.dart';
 
 class WeakWall extends Actor {
   WeakWall(opacity, {super.position}) : super("lock.png");
-
-  @override
-  Future<bool> hit(Direction dir) async {
-    return false;
-  }
-
-  @override
-  void predictedHit(Vector2 startOfMovement, Direction dir) {}
 }
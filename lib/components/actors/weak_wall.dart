@@ -7,7 +7,6 @@ import 'package:flame/particles.dart';
 import 'package:flutter/material.dart';
 import 'package:icedash/components/actor.dart';
 import 'package:icedash/main.dart';
-import 'package:icedash/src/rust/api/direction.dart';
 
 class PixelParticle extends Particle {
   final Paint paint;
@@ -30,9 +29,7 @@ class WeakWall extends Actor {
 
   WeakWall({super.position}) : super("weakwall.png");
 
-  @override
-  Future<bool> hit(Direction dir) async {
-    super.colision = false;
+  void shatter() {
     super.selffade = true;
 
     super.display?.removeFromParent();
@@ -98,10 +95,5 @@ class WeakWall extends Actor {
         },
       ),
     );
-
-    return true;
   }
-
-  @override
-  void predictedHit(Vector2 startOfMovement, Direction dir) {}
 }
@@ -3,11 +3,13 @@ import 'dart:math';
 import 'package:flame/components.dart';
 import 'package:flame/effects.dart';
 import 'package:flutter/services.dart';
+import 'package:icedash/components/room.dart';
 import 'package:icedash/config.dart';
-import 'package:icedash/extensions.dart';
 import 'package:icedash/game.dart';
 import 'package:icedash/main.dart';
 import 'package:icedash/src/rust/api/direction.dart';
+import 'package:icedash/src/rust/api/game_session.dart';
+import 'package:icedash/src/rust/api/pos.dart';
 import 'package:icedash/src/rust/api/tile.dart';
 
 class Player extends SpriteComponent with HasGameReference<IceDashGame> {
@@ -56,37 +58,17 @@ class Player extends SpriteComponent with HasGameReference<IceDashGame> {
 
   bool moving = false;
 
-  void reset() {
+  void reset() async {
     if (moving) {
       return;
     }
     movementQueue = [];
-    game.idWorld.reset();
+    await game.idWorld.reset();
     position = game.idWorld.resetPlayerPos();
     push(game.idWorld.getResetDirection());
     remainingMoves = remainingMovesReset;
   }
 
-  Future<Vector2> predictHit(Direction dir) async {
-    Vector2 cursor = position;
-    Vector2 delta = dir.dartVector();
-    bool firstPush = true;
-
-    while ((await game.idWorld.canMove(
-      cursor,
-      cursor + delta,
-      dir,
-      firstPush,
-    ))) {
-      firstPush = false;
-      cursor = cursor + delta;
-    }
-
-    game.idWorld.predictedHit(position, cursor + delta, dir);
-
-    return cursor;
-  }
-
   void animate(Direction dir, double secondsToHit) {
     late String axis;
 
@@ -162,39 +144,61 @@ class Player extends SpriteComponent with HasGameReference<IceDashGame> {
     Direction dir = movementQueue.first;
     movementQueue.removeAt(0);
 
-    Vector2 destination = await predictHit(dir);
+    RoomComponent room = game.idWorld.currentRoom;
 
-    int movementLenght = (destination - position).length.floor();
+    // Until they walk in the player waits outside the room, next to the gate
+    // the session starts on
+    Pos start = await room.sessionPosition();
+    if (await room.worldVector2MapPos(position) != start &&
+        dir != room.entranceDirection) {
+      moving = false;
+      movementDispatch();
+      return;
+    }
 
-    animate(dir, movementLenght * secPerStep);
+    int movesBefore = room.moveCount;
+    MoveOutcome outcome = await room.applyMove(dir);
+    bool moved = outcome.moveCount != movesBefore;
+    bool exiting = outcome.gateReached != null;
 
-    MoveToEffect effect = MoveToEffect(
-      destination,
-      LinearEffectController(movementLenght * secPerStep),
-      onComplete: () async {
-        moving = false;
+    Vector2 cursor = room.mapPos2WorldVector(start);
+    double elapsed = (cursor - position).length * secPerStep;
+    List<Effect> legs = [MoveToEffect(cursor, LinearEffectController(elapsed))];
+    List<double> arrivals = [];
+
+    for (var (i, p) in outcome.path.indexed) {
+      Vector2 target = room.mapPos2WorldVector(p);
+
+      // Coming out of the twin portal takes no time
+      bool teleported =
+          i > 0 && outcome.teleports.contains((outcome.path[i - 1], p));
+      double duration = teleported ? 0 : (target - cursor).length * secPerStep;
 
-        Tile hitTile = await game.idWorld.getTile(position + dir.dartVector());
+      legs.add(MoveToEffect(target, LinearEffectController(duration)));
+      elapsed += duration;
+      arrivals.add(elapsed);
+      cursor = target;
+    }
+
+    room.playOutcome(outcome, arrivals, dir);
+    animate(dir, elapsed);
 
-        bool consecuences = await game.idWorld.hit(
-          position + dir.dartVector(),
-          dir,
-        );
-        bool exiting = hitTile is Tile_Outside;
+    SequenceEffect effect = SequenceEffect(
+      legs,
+      onComplete: () {
+        moving = false;
 
         int moveI = remainingMoves == null ? 1 : remainingMoves!;
 
         String audio = 'move_${min(moveI, 17)}.mp3';
 
-        if ((movementLenght != 0 || consecuences) && !exiting) {
+        if (moved && !exiting) {
           if (remainingMoves != null) {
             remainingMoves = remainingMoves! - 1;
             // print("remaining moves: $remainingMoves");
             if (remainingMoves == 0) {
-              if (hitTile is! Tile_Outside) {
-                audio = "too_many_moves.mp3";
-                reset();
-              }
+              audio = "too_many_moves.mp3";
+              reset();
             }
           }
 
@@ -10,10 +10,12 @@ import 'package:icedash/components/actors/entrance.dart';
 import 'package:icedash/components/actors/gate.dart';
 import 'package:icedash/components/actors/weak_wall.dart';
 import 'package:icedash/components/sign.dart';
+import 'package:icedash/config.dart';
 import 'package:icedash/extensions.dart';
 import 'package:icedash/game.dart';
 import 'package:icedash/src/rust/api/dart_board.dart';
 import 'package:icedash/src/rust/api/direction.dart';
+import 'package:icedash/src/rust/api/game_session.dart';
 import 'package:icedash/src/rust/api/main.dart';
 import 'package:icedash/src/rust/api/pos.dart';
 import 'package:icedash/src/rust/api/tile.dart';
@@ -31,6 +33,11 @@ class RoomComponent extends Component with HasGameReference<IceDashGame> {
 
   int entranceGateId;
 
+  // The rules engine the solver uses, every move the player makes goes
+  // through it
+  late final Future<GameSession> session;
+  int moveCount = 0;
+
   Vector2 mapPos2WorldVector(Pos p) {
     return p.dartVector() - entranceRoomPos + entranceWorldPos;
   }
@@ -41,8 +48,10 @@ class RoomComponent extends Component with HasGameReference<IceDashGame> {
     return Pos(x: x, y: y);
   }
 
-  void reset() {
-    buildSpriteGrid(1);
+  Future<void> reset() async {
+    await (await session).reset();
+    moveCount = 0;
+    await buildSpriteGrid(1);
   }
 
   late Rect worldBB;
@@ -54,6 +63,7 @@ class RoomComponent extends Component with HasGameReference<IceDashGame> {
     this.entranceGateId,
   ) {
     entranceRoomPos = room.gatePositions[entranceGateId].dartVector();
+    session = GameSession.newInstance(board: room, entryGateId: entranceGateId);
 
     worldBB = Rect.fromLTWH(
       entranceWorldPos.x - (entranceRoomPos).x - 0.5,
@@ -257,7 +267,7 @@ class RoomComponent extends Component with HasGameReference<IceDashGame> {
               add(entrance);
             }
           } else if (tile is Tile_Box) {
-            var box = Box(this, position: mapPos2WorldVector(pos));
+            var box = Box(position: mapPos2WorldVector(pos));
             box.opacity = startingOpacity;
             actorList.add(box);
             add(box);
@@ -285,64 +295,108 @@ class RoomComponent extends Component with HasGameReference<IceDashGame> {
     clean();
   }
 
-  Future<bool> canMove(
-    Vector2 og,
-    Vector2 dst,
+  Future<MoveOutcome> applyMove(Direction dir) async {
+    MoveOutcome outcome = await (await session).applyMove(direction: dir);
+    moveCount = outcome.moveCount;
+    return outcome;
+  }
+
+  Future<Pos> sessionPosition() async {
+    return (await session).getPosition();
+  }
+
+  // Shows what the session did to the board during a move. `arrivals` holds
+  // the seconds it takes the player to reach each tile of the path.
+  Future<void> playOutcome(
+    MoveOutcome outcome,
+    List<double> arrivals,
     Direction dir,
-    bool firstPush,
   ) async {
-    Tile ogTile = await getTile(og);
+    double end = arrivals.isEmpty ? 0 : arrivals.last;
 
-    if (ogTile is Tile_Stop && !firstPush) {
-      return false;
+    if (outcome.gateReached != null) {
+      for (var gate in actorList.whereType<Gate>()) {
+        if (gate.gateId == outcome.gateReached) {
+          gate.enter(end, dir);
+        }
+      }
     }
 
-    if (await ogTile.stopsPlayerDuringGameplay()) {
-      return true;
+    for (var wall in outcome.brokenWalls) {
+      Actor? weakWall = await actorAt(wall);
+      if (weakWall is WeakWall) {
+        after(end, weakWall.shatter);
+      }
     }
 
-    Tile dstTile = await getTile(dst);
-    var canWalk = !(await dstTile.stopsPlayerDuringGameplay());
-
-    if (canWalk == true) {
-      for (var actor in actorList) {
-        if (await worldVector2MapPos(actor.position) ==
-            await worldVector2MapPos(dst)) {
-          canWalk &= !actor.colision;
+    // Keys are picked up as the player slides over them, and their locks
+    // open right away so the same slide can go through
+    for (var key in outcome.pickedKeys) {
+      Tile keyTile = await room.at(p: key);
+      List<Pos> locks = [];
+
+      for (var lock in outcome.openedLocks) {
+        Tile lockTile = await room.at(p: lock);
+        if (keyTile is Tile_Key &&
+            lockTile is Tile_Lock &&
+            lockTile.key == keyTile.id) {
+          locks.add(lock);
         }
       }
-    }
 
-    return canWalk;
-  }
+      after(arrivals[outcome.path.indexOf(key)], () {
+        setTileSprite(key, 'ice.png');
+        for (var lock in locks) {
+          setTileSprite(lock, 'ice.png');
+        }
+      });
+    }
 
-  Future<bool> canBoxWalkInto(Vector2 dst, Direction dir) async {
-    Tile dstTile = await getTile(dst);
-    var canWalk = !(await dstTile.stopsBoxDuringGameplay());
+    // Cracked ice breaks once the player reaches the next tile, the tile they
+    // started on is not part of the path
+    for (var ice in outcome.crackedIce) {
+      after(arrivals[outcome.path.indexOf(ice) + 1], () {
+        setTileSprite(ice, '1x1_obstacle.png');
+      });
+    }
 
-    if (canWalk == true) {
-      for (var actor in actorList) {
-        if (await worldVector2MapPos(actor.position) ==
-            await worldVector2MapPos(dst)) {
-          canWalk &= !actor.colision;
-        }
+    // Each box starts sliding once the one pushing it got there
+    List<(Box, Vector2)> slides = [];
+    for (var (from, to) in outcome.pushedBoxes) {
+      Actor? box = await actorAt(from);
+      if (box is Box) {
+        slides.add((box, mapPos2WorldVector(to)));
       }
     }
 
-    return canWalk;
+    double delay = end;
+    for (var (box, destination) in slides) {
+      after(delay, () => box.slideTo(destination));
+      delay += (destination - box.position).length * secPerStep;
+    }
   }
 
-  Future<bool> hit(Vector2 pos, Direction dir, {bool box = false}) async {
-    var consecuences = false;
+  void after(double seconds, void Function() callback) {
+    add(
+      FunctionEffect(
+        (_, __) {},
+        LinearEffectController(seconds),
+        onComplete: callback,
+      ),
+    );
+  }
+
+  void setTileSprite(Pos pos, String asset) async {
+    tileSpriteGrid[pos]?.sprite = await Sprite.load(asset);
+  }
+
+  Future<Actor?> actorAt(Pos pos) async {
     for (var actor in actorList) {
-      if (await worldVector2MapPos(actor.position) ==
-          await worldVector2MapPos(pos)) {
-        if (!box || actor is Box) {
-          consecuences |= await actor.hit(dir);
-        }
+      if (await worldVector2MapPos(actor.position) == pos) {
+        return actor;
       }
     }
-    return consecuences;
+    return null;
   }
 
   Future<Tile> getTile(Vector2 worldPos) async {
@@ -354,15 +408,6 @@ class RoomComponent extends Component with HasGameReference<IceDashGame> {
       return Tile.outside();
     }
   }
-
-  void predictedHit(Vector2 og, Vector2 pos, Direction dir) async {
-    for (var actor in actorList) {
-      if (await worldVector2MapPos(actor.position) ==
-          await worldVector2MapPos(pos)) {
-        actor.predictedHit(og, dir);
-      }
-    }
-  }
 }
 
 // TODO optimize await worldVector2MapPos(actor.position) == await worldVector2MapPos(pos)
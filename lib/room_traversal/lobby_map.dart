@@ -91,6 +91,33 @@ A       s       B
             vignetPercentageMin: 10,
             vignetPercentageMax: 15,
             gameMode: GameMode.findExit,
+            maxSolutionDepth: 10,
+            nearOptimalTiers: 2,
+            minOptimalMoves: 0,
+            maxOptimalMoves: 10,
+            fitnessWeights: FitnessWeights(
+              decisionPosition: 100,
+              longMove: 10,
+              longMoveThreshold: 3,
+              moveLength: 10,
+              routeLength: 1,
+              weakWallPenalty: 1,
+              boxPenalty: 1,
+              usedStop: 100,
+            ),
+            fitnessFunction: 0,
+            minDifficulty: 0,
+            maxDifficulty: 100,
+            keyLockPairsMin: 0,
+            keyLockPairsMax: 0,
+            portalPercentageMin: 0,
+            portalPercentageMax: 0,
+            arrowPercentageMin: 0,
+            arrowPercentageMax: 0,
+            stopPercentageMin: 0,
+            stopPercentageMax: 0,
+            crackedIcePercentageMin: 0,
+            crackedIcePercentageMax: 0,
           ),
           boardCount: 1 + lev,
           endOfGameMetadata: EndOfGameMetadata(
@@ -115,6 +142,33 @@ A       s       B
             vignetPercentageMin: 10,
             vignetPercentageMax: 15,
             gameMode: GameMode.findPerfectPath,
+            maxSolutionDepth: 10,
+            nearOptimalTiers: 2,
+            minOptimalMoves: 0,
+            maxOptimalMoves: 10,
+            fitnessWeights: FitnessWeights(
+              decisionPosition: 100,
+              longMove: 10,
+              longMoveThreshold: 3,
+              moveLength: 10,
+              routeLength: 1,
+              weakWallPenalty: 1,
+              boxPenalty: 1,
+              usedStop: 100,
+            ),
+            fitnessFunction: 0,
+            minDifficulty: 0,
+            maxDifficulty: 100,
+            keyLockPairsMin: 0,
+            keyLockPairsMax: 0,
+            portalPercentageMin: 0,
+            portalPercentageMax: 0,
+            arrowPercentageMin: 0,
+            arrowPercentageMax: 0,
+            stopPercentageMin: 0,
+            stopPercentageMax: 0,
+            crackedIcePercentageMin: 0,
+            crackedIcePercentageMax: 0,
           ),
           boardCount: 1 + lev,
           endOfGameMetadata: EndOfGameMetadata(
@@ -1,9 +1,15 @@
+import 'dart:typed_data';
+
+import 'package:flutter/services.dart';
 import 'package:icedash/main.dart';
 import 'package:icedash/room_traversal/lobby_map.dart';
 import 'package:icedash/room_traversal/single_rooms.dart';
+import 'package:icedash/src/rust/api/board_description.dart';
 import 'package:icedash/src/rust/api/dart_board.dart';
 import 'package:icedash/src/rust/api/direction.dart';
+import 'package:icedash/src/rust/api/generator.dart';
 import 'package:icedash/src/rust/api/main.dart';
+import 'package:path_provider/path_provider.dart';
 import 'package:shared_preferences/shared_preferences.dart';
 
 enum RoomType { lobby, game }
@@ -20,22 +26,127 @@ class RoomTraversal {
   double start = 0;
   EndOfGameMetadata? endOfGameMetadata;
 
+  static const levelPackAsset = 'assets/packs/levels.pack';
+
+  GeneratorConfig? _generatorConfig;
+
+  // Unserved boards are kept between sessions in the app support directory,
+  // platforms without one just don't keep them
+  Future<GeneratorConfig> generatorConfig() async {
+    if (_generatorConfig != null) {
+      return _generatorConfig!;
+    }
+
+    String? bufferDir;
+    try {
+      bufferDir = (await getApplicationSupportDirectory()).path;
+    } catch (_) {
+      bufferDir = null;
+    }
+
+    // Boards generated offline with icedash-cli are served before anything
+    // generated live, builds without a pack generate everything
+    Uint8List? levelPack;
+    try {
+      levelPack = (await rootBundle.load(levelPackAsset)).buffer.asUint8List();
+    } catch (_) {
+      levelPack = null;
+    }
+
+    return _generatorConfig = GeneratorConfig(
+      dutyCycle: 1,
+      threadCount: null,
+      timeBudgetMillis: null,
+      stagnationLimit: null,
+      bufferDir: bufferDir,
+      levelPack: levelPack,
+    );
+  }
+
+  // How long the player may wait at a gate before a weaker board is served
+  int boardTimeoutMillis = 2000;
+
+  GeneratorHandle? generator;
+  Map<BoardDescription, GeneratorHandle> pregenerated = {};
+
+  // Starts generating for every game reachable from the room, so the first
+  // board is likely ready by the time the player walks through a gate.
+  Future<void> pregenerate(DartBoard room) async {
+    var destinations = room.gateDestinations
+        .whereType<GateDestination_FirstAutogen>()
+        .toList();
+
+    if (destinations.isEmpty) {
+      return;
+    }
+
+    pregenerated.removeWhere((boardDescription, handle) {
+      var stale = destinations.every(
+        (d) => d.boardDescription != boardDescription,
+      );
+      if (stale) {
+        handle.dispose();
+      }
+      return stale;
+    });
+
+    for (var destination in destinations) {
+      if (!pregenerated.containsKey(destination.boardDescription)) {
+        pregenerated[destination.boardDescription] =
+            await GeneratorHandle.newInstance(
+              boardDesc: destination.boardDescription,
+              maxBufferedBoards: destination.boardCount,
+              seed: null,
+              config: await generatorConfig(),
+            );
+      }
+    }
+  }
+
+  void haltGeneration(int millis) {
+    generator?.halt(millis: BigInt.from(millis));
+
+    for (var handle in pregenerated.values) {
+      handle.halt(millis: BigInt.from(millis));
+    }
+  }
+
   Future<(DartBoard, int)> getRoom(
     GateDestination gateDestination,
     Direction entryDirection,
   ) async {
     if (gateDestination is GateDestination_FirstAutogen) {
-      await dartStartSearch(
-        boardDesc: gateDestination.boardDescription,
-        maxBufferedBoards: gateDestination.boardCount,
-      );
+      generator?.dispose();
+      generator =
+          pregenerated.remove(gateDestination.boardDescription) ??
+          await GeneratorHandle.newInstance(
+            boardDesc: gateDestination.boardDescription,
+            maxBufferedBoards: gateDestination.boardCount,
+            seed: null,
+            config: await generatorConfig(),
+          );
+
+      for (var handle in pregenerated.values) {
+        handle.dispose();
+      }
+      pregenerated.clear();
     }
 
     if (gateDestination is GateDestination_NextAutoGen ||
         gateDestination is GateDestination_FirstAutogen) {
-      AutoGenOutput ret = await dartGetNewBoard(entryDirection: entryDirection);
+      BestEffortOutput ret = await generator!.getNewBoardWithin(
+        entryDirection: entryDirection,
+        timeoutMillis: boardTimeoutMillis,
+      );
 
-      if (ret is AutoGenOutput_Ok) {
+      DartBoard? board = switch (ret) {
+        BestEffortOutput_Buffered(:final field0) => field0,
+        BestEffortOutput_BelowThreshold(:final field0) => field0,
+        BestEffortOutput_Fallback(:final field0) => field0,
+        _ => null,
+      };
+
+      if (board != null) {
         if (gateDestination is GateDestination_FirstAutogen) {
           endOfGameMetadata = gateDestination.endOfGameMetadata;
 
@@ -46,8 +157,8 @@ class RoomTraversal {
           playAudio('won_room.mp3');
         }
 
-        return (ret.field0, 0);
-      } else if (ret is AutoGenOutput_NoMoreBufferedBoards) {
+        return (board, 0);
+      } else if (ret is BestEffortOutput_NoMoreBufferedBoards) {
         playAudio('won_strech.mp3');
         return (
           await endOfGameRoom(
@@ -61,7 +172,10 @@ class RoomTraversal {
     } else if (gateDestination is GateDestination_RoomIdWithGate) {
       playAudio('change_room.mp3');
 
-      return lobbyRoom(gateDestination, entryDirection);
+      var room = await lobbyRoom(gateDestination, entryDirection);
+      await pregenerate(room.$1);
+
+      return room;
     }
 
     return (await turnRoom(gateDestination, entryDirection), 0);
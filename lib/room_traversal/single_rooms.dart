@@ -4,7 +4,7 @@ import 'package:icedash/src/rust/api/main.dart';
 
 Future<DartBoard> errorRoom(Direction entranceDirection) {
   return DartBoard.newLobby(
-    serialized: ''' 
+    serialized: '''
 # # # # # 
 #   S   # 
 # # # # # 
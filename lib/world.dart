@@ -164,26 +164,14 @@ class IceDashWorld extends World with HasGameReference {
     camera = cam;
   }
 
-  Future<bool> canMove(
-    Vector2 og,
-    Vector2 dst,
-    Direction dir,
-    bool firstPush,
-  ) async {
-    bool ret = await _currentRoom!.canMove(og, dst, dir, firstPush);
-    return ret;
-  }
-
-  Future<bool> hit(Vector2 pos, Direction dir) async {
-    return _currentRoom!.hit(pos, dir);
-  }
+  RoomComponent get currentRoom => _currentRoom!;
 
   Future<Tile> getTile(Vector2 position) async {
     return await (_currentRoom!.getTile(position));
   }
 
-  reset() {
-    _currentRoom!.reset();
+  Future<void> reset() async {
+    await _currentRoom!.reset();
   }
 
   Direction getResetDirection() {
@@ -194,10 +182,6 @@ class IceDashWorld extends World with HasGameReference {
     return _currentRoom!.entranceWorldPos;
   }
 
-  void predictedHit(Vector2 startingPos, Vector2 hitPos, Direction dir) {
-    return _currentRoom!.predictedHit(startingPos, hitPos, dir);
-  }
-
   double timeElapsed = 0;
   static const timePerFrame = 1 / 30;
 
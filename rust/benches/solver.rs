@@ -0,0 +1,216 @@
+//! Compares the state space solver against the breadth first path search it
+//! replaced. Run with `cargo bench --bench solver`.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    rc::Rc,
+    time::Instant,
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+use rust_lib_icedash::{
+    api::{
+        board_description::{BoardDescription, GameMode},
+        direction::Direction,
+        pos::Pos,
+        tile::Tile,
+    },
+    logic::{
+        board::Board,
+        solver::{analyze, step, SolverLimits},
+    },
+};
+
+const BOARDS_PER_DESCRIPTION: u64 = 300;
+const LEGACY_TOP_SOLUTION_SIZE: usize = 10;
+const REPETITIONS: u32 = 5;
+
+type Solution = Vec<(Direction, Pos)>;
+
+// The previous solver: every path is its own search state, positions visited
+// since the last board change are kept per path and depth is capped at 10.
+fn legacy_analyze(board: &Board) -> Option<(usize, Vec<Vec<Solution>>)> {
+    const EXTRA_MOVES_SEARCH_MARGIN: usize = 3;
+
+    struct State {
+        board: Rc<Board>,
+        path: Solution,
+        position: Pos,
+        directions: Vec<Direction>,
+        visitations: HashSet<Pos>,
+    }
+
+    let exit = board.get_gate_position(1);
+    let start = board.get_gate_position(0);
+
+    let mut states = VecDeque::from([State {
+        board: Rc::new(board.clone()),
+        path: vec![],
+        position: start,
+        directions: vec![board.get_gate_direction(0)],
+        visitations: HashSet::from([start]),
+    }]);
+
+    let mut solutions = vec![vec![]; EXTRA_MOVES_SEARCH_MARGIN];
+    let mut best = None;
+
+    while let Some(state) = states.pop_front() {
+        for direction in &state.directions {
+            let new_step = step(&state.board.map, &state.position, direction);
+
+            let mut new_board = Rc::clone(&state.board);
+            let mut visitations = state.visitations.clone();
+            let changes_board = new_step.hit.changes_board_on_hit();
+
+            if changes_board {
+                Rc::make_mut(&mut new_board).resolve_hit(&new_step.hit_pos, direction);
+                visitations.clear();
+            }
+
+            if !visitations.insert(new_step.pos) {
+                continue;
+            }
+
+            let mut path = state.path.clone();
+            path.push((*direction, new_step.pos));
+
+            if new_step.pos == exit {
+                let best = *best.get_or_insert(path.len());
+                solutions[path.len() - best].push(path);
+            } else if best.map_or(LEGACY_TOP_SOLUTION_SIZE > path.len(), |best| {
+                path.len() < best + EXTRA_MOVES_SEARCH_MARGIN - 1
+            }) {
+                states.push_back(State {
+                    board: new_board,
+                    position: new_step.pos,
+                    directions: if changes_board || new_step.hit == Tile::Stop {
+                        vec![
+                            Direction::North,
+                            Direction::East,
+                            Direction::South,
+                            Direction::West,
+                        ]
+                    } else {
+                        vec![direction.left(), direction.right()]
+                    },
+                    visitations,
+                    path,
+                });
+            }
+        }
+    }
+
+    best.map(|best| (best, solutions))
+}
+
+fn main() {
+    let descriptions = [
+        (
+            "small find exit",
+            BoardDescription {
+                pilars_percentage_min: 5,
+                pilars_percentage_max: 10,
+                ..BoardDescription::default()
+            },
+        ),
+        (
+            "small perfect path",
+            BoardDescription {
+                weak_walls_percentage_max: 5,
+                pilars_percentage_max: 5,
+                box_percentage_max: 3,
+                game_mode: GameMode::FindPerfectPath,
+                ..BoardDescription::default()
+            },
+        ),
+        (
+            "large perfect path",
+            BoardDescription {
+                area: 150,
+                weak_walls_percentage_max: 5,
+                pilars_percentage_max: 5,
+                box_percentage_max: 3,
+                game_mode: GameMode::FindPerfectPath,
+                ..BoardDescription::default()
+            },
+        ),
+        (
+            "boxes and weak walls",
+            BoardDescription {
+                area: 100,
+                weak_walls_percentage_min: 5,
+                weak_walls_percentage_max: 10,
+                pilars_percentage_max: 5,
+                box_percentage_min: 3,
+                box_percentage_max: 6,
+                game_mode: GameMode::FindPerfectPath,
+                ..BoardDescription::default()
+            },
+        ),
+    ];
+
+    for (name, description) in descriptions {
+        let boards: Vec<Board> = (0..BOARDS_PER_DESCRIPTION)
+            .filter_map(|seed| {
+                Board::new_random(&description, &mut StdRng::seed_from_u64(seed)).ok()
+            })
+            .collect();
+
+        let mut solved = 0;
+        let mut only_new = 0;
+
+        for board in &boards {
+            match (
+                legacy_analyze(board),
+                analyze(board, 0, 1, &SolverLimits::default()),
+            ) {
+                (Some((best, routes)), Ok(analysis)) => {
+                    solved += 1;
+                    assert_eq!(best, analysis.optimal_movement_count);
+
+                    // The legacy search stops expanding at depth 10, so near
+                    // optimal tiers past that are not comparable
+                    for (tier, routes) in routes.iter().enumerate() {
+                        if best + tier < LEGACY_TOP_SOLUTION_SIZE {
+                            let new_routes: Vec<_> = analysis.routes[tier]
+                                .iter()
+                                .map(|route| route.solution.clone())
+                                .collect();
+                            assert_eq!(routes, &new_routes);
+                        }
+                    }
+                }
+                (None, Ok(_)) => only_new += 1,
+                (Some(_), Err(e)) => panic!("new solver failed on a solvable board: {e}"),
+                (None, Err(_)) => {}
+            }
+        }
+
+        let t = Instant::now();
+        for _ in 0..REPETITIONS {
+            boards.iter().for_each(|board| {
+                legacy_analyze(board);
+            });
+        }
+        let legacy_time = t.elapsed() / REPETITIONS;
+
+        let t = Instant::now();
+        for _ in 0..REPETITIONS {
+            boards.iter().for_each(|board| {
+                let _ = analyze(board, 0, 1, &SolverLimits::default());
+            });
+        }
+        let new_time = t.elapsed() / REPETITIONS;
+
+        println!(
+            "{name}: {} boards, {solved} solved by both, {only_new} only by the new solver",
+            boards.len()
+        );
+        println!(
+            "    legacy {:>10.2?}  new {:>10.2?}  speedup x{:.1}",
+            legacy_time,
+            new_time,
+            legacy_time.as_secs_f64() / new_time.as_secs_f64()
+        );
+    }
+}
@@ -1,9 +1,11 @@
+use rand::Rng;
+
 use crate::{api::{direction::Direction, pos::Pos, tile::Tile}, logic::matrix::{Matrix, TileMap}};
 
 pub type AssetMap = Matrix<Option<(String, isize)>>;
 
 impl Matrix<Option<(String, isize)>> {
-   pub fn from_tilemap(tilemap: &TileMap) -> Self {
+   pub fn from_tilemap(tilemap: &TileMap, rng: &mut impl Rng) -> Self {
         let mut ret = Matrix::new(tilemap.get_width(), tilemap.get_height());
         let mut wip_tilemap = tilemap.clone();
 
@@ -61,7 +63,7 @@ impl Matrix<Option<(String, isize)>> {
 
         for p in wip_tilemap.all_pos() {
             if let None = ret.at(&p) {
-                ret.set(&p, wip_tilemap.neighbour_at(&p).get_asset(p));
+                ret.set(&p, wip_tilemap.neighbour_at(&p).get_asset(p, rng));
             }
         }
 
@@ -1,5 +1,7 @@
-#[derive(Clone, PartialEq, Debug, Eq, Hash)]
+#[derive(Clone, PartialEq, Debug, Eq, Hash, Default)]
+#[cfg_attr(feature = "cli", derive(serde::Deserialize))]
 pub enum GameMode {
+    #[default]
     FindExit,
     FindPerfectPath,
 }
@@ -24,7 +26,14 @@ impl From<&GameMode> for isize {
     }
 }
 
+// The command line tool reads descriptions as JSON, fields left out take their
+// default
 #[derive(Clone, PartialEq, Debug, Eq, Hash)]
+#[cfg_attr(
+    feature = "cli",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct BoardDescription {
     pub area: isize,
     pub weak_walls_percentage_min: isize,
@@ -36,9 +45,100 @@ pub struct BoardDescription {
     pub vignet_percentage_min: isize,
     pub vignet_percentage_max: isize,
     pub game_mode: GameMode,
+    pub max_solution_depth: isize,
+    pub near_optimal_tiers: isize,
+    pub min_optimal_moves: isize,
+    pub max_optimal_moves: isize,
+    pub fitness_weights: FitnessWeights,
+    // 0 scores rooms with the game mode's built-in fitness function, any other
+    // id with the one registered under it
+    pub fitness_function: isize,
+    pub min_difficulty: isize,
+    pub max_difficulty: isize,
+    pub key_lock_pairs_min: isize,
+    pub key_lock_pairs_max: isize,
+    pub portal_percentage_min: isize,
+    pub portal_percentage_max: isize,
+    pub arrow_percentage_min: isize,
+    pub arrow_percentage_max: isize,
+    pub stop_percentage_min: isize,
+    pub stop_percentage_max: isize,
+    pub cracked_ice_percentage_min: isize,
+    pub cracked_ice_percentage_max: isize,
+}
+
+#[derive(Clone, PartialEq, Debug, Eq, Hash)]
+#[cfg_attr(
+    feature = "cli",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct FitnessWeights {
+    pub decision_position: isize,
+    pub long_move: isize,
+    pub long_move_threshold: isize,
+    pub move_length: isize,
+    pub route_length: isize,
+    pub weak_wall_penalty: isize,
+    pub box_penalty: isize,
+    pub used_stop: isize,
+}
+
+impl Default for FitnessWeights {
+    fn default() -> Self {
+        Self {
+            decision_position: 100,
+            long_move: 10,
+            long_move_threshold: 3,
+            move_length: 10,
+            route_length: 1,
+            weak_wall_penalty: 1,
+            box_penalty: 1,
+            used_stop: 100,
+        }
+    }
+}
+
+// A plain ice room of the size of the first lobby level, with the lobby's
+// solver settings
+impl Default for BoardDescription {
+    fn default() -> Self {
+        Self {
+            area: 49,
+            weak_walls_percentage_min: 0,
+            weak_walls_percentage_max: 0,
+            pilars_percentage_min: 0,
+            pilars_percentage_max: 0,
+            box_percentage_min: 0,
+            box_percentage_max: 0,
+            vignet_percentage_min: 10,
+            vignet_percentage_max: 15,
+            game_mode: GameMode::FindExit,
+            max_solution_depth: 10,
+            near_optimal_tiers: 2,
+            min_optimal_moves: 0,
+            max_optimal_moves: 10,
+            fitness_weights: FitnessWeights::default(),
+            fitness_function: 0,
+            min_difficulty: 0,
+            max_difficulty: 100,
+            key_lock_pairs_min: 0,
+            key_lock_pairs_max: 0,
+            portal_percentage_min: 0,
+            portal_percentage_max: 0,
+            arrow_percentage_min: 0,
+            arrow_percentage_max: 0,
+            stop_percentage_min: 0,
+            stop_percentage_max: 0,
+            cracked_ice_percentage_min: 0,
+            cracked_ice_percentage_max: 0,
+        }
+    }
 }
 
 impl BoardDescription {
+    pub(crate) const LIST_LEN: usize = 35;
+
     pub fn from_list(data: Vec<isize>) -> BoardDescription {
         BoardDescription {
             area: data[0],
@@ -51,6 +151,33 @@ impl BoardDescription {
             vignet_percentage_min: data[7],
             vignet_percentage_max: data[8],
             game_mode: data[9].into(),
+            max_solution_depth: data[10],
+            near_optimal_tiers: data[11],
+            min_optimal_moves: data[12],
+            max_optimal_moves: data[13],
+            fitness_weights: FitnessWeights {
+                decision_position: data[14],
+                long_move: data[15],
+                long_move_threshold: data[16],
+                move_length: data[17],
+                route_length: data[18],
+                weak_wall_penalty: data[19],
+                box_penalty: data[20],
+                used_stop: data[21],
+            },
+            min_difficulty: data[22],
+            max_difficulty: data[23],
+            key_lock_pairs_min: data[24],
+            key_lock_pairs_max: data[25],
+            portal_percentage_min: data[26],
+            portal_percentage_max: data[27],
+            arrow_percentage_min: data[28],
+            arrow_percentage_max: data[29],
+            stop_percentage_min: data[30],
+            stop_percentage_max: data[31],
+            cracked_ice_percentage_min: data[32],
+            cracked_ice_percentage_max: data[33],
+            fitness_function: data[34],
         }
     }
 
@@ -66,6 +193,43 @@ impl BoardDescription {
             self.vignet_percentage_min,
             self.vignet_percentage_max,
             (&self.game_mode).into(),
+            self.max_solution_depth,
+            self.near_optimal_tiers,
+            self.min_optimal_moves,
+            self.max_optimal_moves,
+            self.fitness_weights.decision_position,
+            self.fitness_weights.long_move,
+            self.fitness_weights.long_move_threshold,
+            self.fitness_weights.move_length,
+            self.fitness_weights.route_length,
+            self.fitness_weights.weak_wall_penalty,
+            self.fitness_weights.box_penalty,
+            self.fitness_weights.used_stop,
+            self.min_difficulty,
+            self.max_difficulty,
+            self.key_lock_pairs_min,
+            self.key_lock_pairs_max,
+            self.portal_percentage_min,
+            self.portal_percentage_max,
+            self.arrow_percentage_min,
+            self.arrow_percentage_max,
+            self.stop_percentage_min,
+            self.stop_percentage_max,
+            self.cracked_ice_percentage_min,
+            self.cracked_ice_percentage_max,
+            self.fitness_function,
         ]
     }
+
+    pub(crate) fn allows_optimal_movement_count(&self, movement_count: usize) -> bool {
+        (self.min_optimal_moves..=self.max_optimal_moves).contains(&(movement_count as isize))
+    }
+
+    pub(crate) fn restricts_difficulty(&self) -> bool {
+        self.min_difficulty > 0 || self.max_difficulty < 100
+    }
+
+    pub(crate) fn allows_difficulty(&self, difficulty: isize) -> bool {
+        (self.min_difficulty..=self.max_difficulty).contains(&difficulty)
+    }
 }
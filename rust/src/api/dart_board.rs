@@ -1,15 +1,23 @@
 use std::{collections::HashMap, ops::Deref};
 
+use rand::{rngs::StdRng, SeedableRng};
+
 use crate::{
     api::{
         asset_map::AssetMap,
-        board_description::GameMode,
+        board_description::{BoardDescription, GameMode},
+        difficulty::DifficultyReport,
         direction::Direction,
+        lobby_text::{parse_lobby, LobbyText},
         main::{GateDestination, GateMetadata, LeftRotatable},
         pos::Pos,
         tile::Tile,
     },
-    logic::{board::Board, gate::GateEntry, matrix::Matrix, solver::Analysis},
+    logic::{
+        board::Board,
+        solver::{Analysis, SolverLimits},
+        worker_pool::generate_seeded_room,
+    },
 };
 
 #[derive(Clone)]
@@ -25,6 +33,12 @@ pub struct DartBoard {
     pub width: isize,
     pub height: isize,
     pub max_movement_count: Option<isize>,
+    pub trap_move_count: Option<isize>,
+    pub difficulty: Option<DifficultyReport>,
+    pub seed: u64,
+    // The limits the room was generated with, so hints search as deep as the
+    // generator did
+    pub(crate) solver_limits: SolverLimits,
 }
 
 impl Deref for DartBoard {
@@ -36,9 +50,15 @@ impl Deref for DartBoard {
 }
 
 impl DartBoard {
-    pub(crate) fn new(board: Board, board_metadata: Option<(Analysis, GameMode)>) -> Self {
+    pub(crate) fn new(
+        board: Board,
+        board_metadata: Option<(Analysis, GameMode)>,
+        difficulty: Option<DifficultyReport>,
+        solver_limits: SolverLimits,
+        seed: u64,
+    ) -> Self {
         Self {
-            asset_map: AssetMap::from_tilemap(&board.map),
+            asset_map: AssetMap::from_tilemap(&board.map, &mut StdRng::seed_from_u64(seed)),
             max_movement_count: match &board_metadata {
                 None => None,
                 Some((_, GameMode::FindExit)) => None,
@@ -46,6 +66,7 @@ impl DartBoard {
                     Some(analysis.optimal_movement_count as isize)
                 }
             },
+            trap_move_count: difficulty.as_ref().map(|report| report.dead_ends),
             gate_directions: board.gates.iter().map(|g| g.inwards_direction).collect(),
             gate_positions: board.gates.iter().map(|g| g.pos).collect(),
             gate_destinations: board
@@ -70,61 +91,75 @@ impl DartBoard {
             height: board.get_height(),
             board_metadata: board_metadata,
             board,
+            difficulty,
+            seed,
+            solver_limits,
         }
     }
 
     pub fn new_lobby(
         serialized: String,
         gate_metadata: HashMap<u8, GateMetadata>,
-        mut sign_text: Vec<(String, isize, isize)>,
+        sign_text: Vec<(String, isize, isize)>,
         entrance_direction: Option<(usize, Direction)>,
-    ) -> Self {
-        let mut map: Vec<Vec<Tile>> = vec![];
-        let mut gates = vec![];
-        let mut x: usize;
-        let mut y: usize = 0;
-
-        for line in serialized.split("\n") {
-            let mut line = line.as_bytes();
-            let mut row = vec![];
-            x = 0;
-
-            while line.len() != 0 {
-                let tile = Tile::from_symbol(line[0], &gate_metadata, &mut sign_text);
-
-                if let Tile::Gate(_) = tile {
-                    gates.push(GateEntry::new(
-                        Pos::new(x as isize, y as isize),
-                        map.get(0).unwrap_or(&vec![]).len() as isize,
-                    ));
-                }
-
-                x += 1;
+    ) -> Result<Self, String> {
+        let board = parse_lobby(&serialized, &gate_metadata, sign_text)?;
 
-                row.push(tile.clone());
+        board.print(vec![]);
 
-                line = &line[2..];
-            }
-            if row.len() != 0 {
-                y += 1;
-                map.push(row)
-            }
-        }
-
-        Matrix(map.clone()).print(vec![]);
+        Self::lobby(board, entrance_direction)
+    }
 
-        let mut board = Board {
-            map: Matrix(map.clone()),
-            gates,
-        };
+    // Unlike new_lobby this keeps the gate ids the board had when it was
+    // written out
+    pub fn new_from_lobby_text(
+        lobby_text: LobbyText,
+        entrance_direction: Option<(usize, Direction)>,
+    ) -> Result<Self, String> {
+        Self::lobby(lobby_text.to_board()?, entrance_direction)
+    }
 
+    fn lobby(
+        mut board: Board,
+        entrance_direction: Option<(usize, Direction)>,
+    ) -> Result<Self, String> {
         if let Some((entrance_gate, entrance_direction)) = entrance_direction {
-            while board.gates[entrance_gate].inwards_direction != entrance_direction {
+            if entrance_gate >= board.gates.len() {
+                return Err(format!(
+                    "entrance gate {entrance_gate} doesn't exist, the lobby has {} gates",
+                    board.gates.len()
+                ));
+            }
+
+            for _ in 0..4 {
+                if board.gates[entrance_gate].inwards_direction == entrance_direction {
+                    break;
+                }
                 board = board.rotate_left()
             }
+
+            if board.gates[entrance_gate].inwards_direction != entrance_direction {
+                return Err(format!(
+                    "entrance gate {entrance_gate} can't be turned to face {entrance_direction:?}"
+                ));
+            }
         }
 
-        Self::new(board, None)
+        Ok(Self::new(
+            board,
+            None,
+            None,
+            SolverLimits::default(),
+            rand::random(),
+        ))
+    }
+
+    pub fn new_seeded(
+        board_desc: BoardDescription,
+        seed: u64,
+        entrance_direction: Direction,
+    ) -> Result<Self, String> {
+        generate_seeded_room(&board_desc, seed, entrance_direction)
     }
 
     pub fn at(&self, p: &Pos) -> Tile {
@@ -142,4 +177,8 @@ impl DartBoard {
     pub fn print(&self) {
         self.board.print(vec![]);
     }
+
+    pub fn to_lobby_text(&self) -> Result<LobbyText, String> {
+        LobbyText::new(&self.board)
+    }
 }
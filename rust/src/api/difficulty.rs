@@ -0,0 +1,237 @@
+use crate::{
+    api::{direction::Direction, pos::Pos},
+    logic::{
+        board::{Board, BoardChange},
+        reachability::count_trap_moves,
+        solver::{step, Analysis, Route},
+    },
+};
+
+// Points are mapped onto 0 to 100 on a saturating curve, a room worth this
+// many points rates 63
+const DIFFICULTY_SCALE: f64 = 30.;
+
+const MOVE_POINTS: f64 = 1.;
+const EXTRA_OPTION_POINTS: f64 = 4.;
+const DECOY_POINTS: f64 = 3.;
+const INTERACTION_POINTS: f64 = 2.;
+const DEAD_END_POINTS: f64 = 3.;
+
+const SECONDS_PER_MOVE: f64 = 1.;
+const SECONDS_PER_OPTION: f64 = 1.5;
+const SECONDS_PER_INTERACTION: f64 = 3.;
+const SECONDS_PER_DEAD_END: f64 = 5.;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DifficultyReport {
+    pub difficulty: isize,
+    pub estimated_solve_seconds: isize,
+    pub optimal_moves: isize,
+    pub branching_factor: f64,
+    pub near_optimal_decoys: isize,
+    pub required_box_pushes: isize,
+    pub required_wall_breaks: isize,
+    pub dead_ends: isize,
+    pub reasons: Vec<String>,
+}
+
+struct RouteReplay {
+    options: Vec<usize>,
+    box_pushes: usize,
+    wall_breaks: usize,
+}
+
+impl DifficultyReport {
+    pub(crate) fn new(
+        board: &Board,
+        analysis: &Analysis,
+        entry_gate_id: usize,
+        exit_gate_id: usize,
+    ) -> Self {
+        let start = board.get_gate_position(entry_gate_id);
+
+        // The optimal route that needs the fewest interactions is the one a
+        // player is expected to find
+        let replay = analysis.routes[0]
+            .iter()
+            .map(|route| replay(board, start, route))
+            .min_by_key(|replay| replay.box_pushes + replay.wall_breaks)
+            .unwrap();
+
+        let optimal_moves = analysis.optimal_movement_count;
+        let branching_factor =
+            replay.options.iter().sum::<usize>() as f64 / replay.options.len().max(1) as f64;
+        let near_optimal_decoys = analysis.routes[1..]
+            .iter()
+            .map(|routes| routes.len())
+            .sum::<usize>();
+        let interactions = replay.box_pushes + replay.wall_breaks;
+        let dead_ends = count_trap_moves(board, entry_gate_id, exit_gate_id);
+
+        let mut contributions = vec![
+            (
+                optimal_moves as f64 * MOVE_POINTS,
+                format!("{optimal_moves} moves on the shortest route"),
+            ),
+            (
+                (branching_factor - 1.).max(0.) * EXTRA_OPTION_POINTS,
+                format!("{branching_factor:.1} options per move along the shortest route"),
+            ),
+            (
+                (1. + near_optimal_decoys as f64).ln() * DECOY_POINTS,
+                format!("{near_optimal_decoys} slightly longer routes to fall into"),
+            ),
+            (
+                interactions as f64 * INTERACTION_POINTS,
+                format!(
+                    "{} box pushes and {} weak walls to break",
+                    replay.box_pushes, replay.wall_breaks
+                ),
+            ),
+            (
+                (1. + dead_ends as f64).ln() * DEAD_END_POINTS,
+                format!("{dead_ends} moves that leave the exit unreachable"),
+            ),
+        ];
+
+        let points = contributions.iter().map(|(points, _)| points).sum::<f64>();
+
+        contributions.retain(|(points, _)| *points > 0.);
+        contributions.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let estimated_solve_seconds = optimal_moves as f64
+            * (SECONDS_PER_MOVE + SECONDS_PER_OPTION * branching_factor)
+            + interactions as f64 * SECONDS_PER_INTERACTION
+            + (1. + dead_ends as f64).ln() * SECONDS_PER_DEAD_END;
+
+        Self {
+            difficulty: (100. * (1. - (-points / DIFFICULTY_SCALE).exp())).round() as isize,
+            estimated_solve_seconds: estimated_solve_seconds.round() as isize,
+            optimal_moves: optimal_moves as isize,
+            branching_factor,
+            near_optimal_decoys: near_optimal_decoys as isize,
+            required_box_pushes: replay.box_pushes as isize,
+            required_wall_breaks: replay.wall_breaks as isize,
+            dead_ends: dead_ends as isize,
+            reasons: contributions
+                .into_iter()
+                .map(|(_, reason)| reason)
+                .collect(),
+        }
+    }
+}
+
+fn replay(board: &Board, start: Pos, route: &Route) -> RouteReplay {
+    let mut board = board.clone();
+    let mut position = start;
+    let mut ret = RouteReplay {
+        options: vec![],
+        box_pushes: 0,
+        wall_breaks: 0,
+    };
+
+    for (direction, _) in &route.solution {
+        ret.options.push(
+            Direction::all()
+                .iter()
+                .filter(|option| {
+                    let step = step(&board.map, &position, option);
+                    step.pos != position || step.changes_board()
+                })
+                .count(),
+        );
+
+        let step = step(&board.map, &position, direction);
+
+        for change in board.resolve_step(&step, direction) {
+            match change {
+                BoardChange::BrokenWall(_) => ret.wall_breaks += 1,
+                BoardChange::PushedBoxes(_) => ret.box_pushes += 1,
+                BoardChange::PickedKey { .. } | BoardChange::CrackedIce(_) | BoardChange::None => {}
+            }
+        }
+
+        position = step.pos;
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        api::{
+            lobby_text::parse_lobby,
+            main::{GateDestination, GateMetadata},
+        },
+        logic::solver::{analyze, SolverLimits},
+    };
+
+    // Gate 0 is the exit, gate 1 the entrance
+    fn report(serialized: &str) -> DifficultyReport {
+        let board = parse_lobby(
+            serialized,
+            &HashMap::from([(
+                b'A',
+                GateMetadata::Exit {
+                    destination: GateDestination::NextAutoGen,
+                    label: None,
+                },
+            )]),
+            vec![],
+        )
+        .unwrap();
+        let analysis = analyze(&board, 1, 0, &SolverLimits::default()).unwrap();
+
+        DifficultyReport::new(&board, &analysis, 1, 0)
+    }
+
+    #[test]
+    fn straight_run_to_the_exit() {
+        let report = report(
+            "# A # # # # # \n\
+             #           # \n\
+             #           # \n\
+             # E # # # # # \n",
+        );
+
+        assert_eq!(report.optimal_moves, 1);
+        assert_eq!(report.branching_factor, 1.);
+        assert_eq!(report.dead_ends, 0);
+        assert_eq!(report.difficulty, 3);
+        assert_eq!(report.reasons, vec!["1 moves on the shortest route"]);
+    }
+
+    #[test]
+    fn every_contribution_is_counted() {
+        let report = report(
+            "# # # # # # # \n\
+             #   w       # \n\
+             #           # \n\
+             #     b     A \n\
+             # E # # # # # \n",
+        );
+
+        assert_eq!(report.optimal_moves, 5);
+        assert_eq!(report.branching_factor, 2.);
+        assert_eq!(report.near_optimal_decoys, 2);
+        assert_eq!(report.required_box_pushes, 0);
+        assert_eq!(report.required_wall_breaks, 1);
+        assert_eq!(report.dead_ends, 2);
+        assert_eq!(report.difficulty, 44);
+        assert_eq!(report.estimated_solve_seconds, 28);
+        assert_eq!(
+            report.reasons,
+            vec![
+                "5 moves on the shortest route",
+                "2.0 options per move along the shortest route",
+                "2 slightly longer routes to fall into",
+                "2 moves that leave the exit unreachable",
+                "0 box pushes and 1 weak walls to break",
+            ]
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::api::pos::Pos;
 
-#[derive(Clone, PartialEq, Copy, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Copy, Debug)]
 pub enum Direction {
     North,
     South,
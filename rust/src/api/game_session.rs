@@ -0,0 +1,497 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    api::{dart_board::DartBoard, direction::Direction, pos::Pos, tile::Tile},
+    logic::{
+        board::{Board, BoardChange},
+        reachability::exit_reachable,
+        solver::{analyze_from, step, Analysis, SolverLimits},
+    },
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MoveOutcome {
+    pub direction: Direction,
+    pub path: Vec<Pos>,
+    pub pushed_boxes: Vec<(Pos, Pos)>,
+    pub broken_walls: Vec<Pos>,
+    pub picked_keys: Vec<Pos>,
+    pub opened_locks: Vec<Pos>,
+    pub teleports: Vec<(Pos, Pos)>,
+    pub cracked_ice: Vec<Pos>,
+    pub gate_reached: Option<isize>,
+    pub move_count: isize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hint {
+    pub next_move: Direction,
+    pub remaining_moves: isize,
+    pub on_optimal_path: bool,
+    pub moves_lost: isize,
+}
+
+// Boards are shared between snapshots until a move mutates them, so a long
+// history of plain slides costs one board.
+#[derive(Clone)]
+struct SessionState {
+    board: Arc<Board>,
+    position: Pos,
+    move_count: usize,
+}
+
+pub struct GameSession {
+    history: Vec<SessionState>,
+    outcomes: Vec<MoveOutcome>,
+    current: usize,
+    solver_limits: SolverLimits,
+    // Optimal movement count from the entry gate, per exit gate, solved the
+    // first time a hint for that exit is asked for
+    initial_optimal_movement_counts: Mutex<HashMap<usize, usize>>,
+}
+
+impl GameSession {
+    pub fn new(board: &DartBoard, entry_gate_id: isize) -> Self {
+        Self {
+            history: vec![SessionState {
+                position: board.get_gate_position(entry_gate_id as usize),
+                board: Arc::new(board.board.clone()),
+                move_count: 0,
+            }],
+            outcomes: vec![],
+            current: 0,
+            solver_limits: board.solver_limits.clone(),
+            // Generated rooms come analyzed from gate 0 to gate 1
+            initial_optimal_movement_counts: Mutex::new(
+                match (&board.board_metadata, entry_gate_id) {
+                    (Some((analysis, _)), 0) => {
+                        HashMap::from([(1, analysis.optimal_movement_count)])
+                    }
+                    _ => HashMap::new(),
+                },
+            ),
+        }
+    }
+
+    fn state(&self) -> &SessionState {
+        &self.history[self.current]
+    }
+
+    pub fn apply_move(&mut self, direction: Direction) -> MoveOutcome {
+        let mut state = self.state().clone();
+        let outcome = state.apply_move(direction);
+
+        // Moves into a wall change nothing and would only clutter the history
+        if state.move_count != self.state().move_count {
+            self.history.truncate(self.current + 1);
+            self.outcomes.truncate(self.current);
+
+            self.history.push(state);
+            self.outcomes.push(outcome.clone());
+            self.current += 1;
+        }
+
+        outcome
+    }
+
+    pub fn undo(&mut self) -> Option<MoveOutcome> {
+        if self.current == 0 {
+            return None;
+        }
+
+        self.current -= 1;
+        Some(self.outcomes[self.current].clone())
+    }
+
+    pub fn redo(&mut self) -> Option<MoveOutcome> {
+        if self.current + 1 >= self.history.len() {
+            return None;
+        }
+
+        self.current += 1;
+        Some(self.outcomes[self.current - 1].clone())
+    }
+
+    pub fn reset(&mut self) {
+        self.history.truncate(1);
+        self.outcomes.clear();
+        self.current = 0;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current != 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.history.len()
+    }
+
+    pub fn get_move_history(&self) -> Vec<MoveOutcome> {
+        self.outcomes[..self.current].to_vec()
+    }
+
+    pub fn hint(&self, exit_gate_id: isize) -> Option<Hint> {
+        let exit_gate_id = exit_gate_id as usize;
+        let analysis = self.state().solve(exit_gate_id, &self.solver_limits)?;
+
+        let cached = self
+            .initial_optimal_movement_counts
+            .lock()
+            .unwrap()
+            .get(&exit_gate_id)
+            .copied();
+        let initial_optimal_movement_count = match cached {
+            Some(count) => count,
+            None => {
+                let count = self.history[0]
+                    .solve(exit_gate_id, &self.solver_limits)?
+                    .optimal_movement_count;
+
+                self.initial_optimal_movement_counts
+                    .lock()
+                    .unwrap()
+                    .insert(exit_gate_id, count);
+                count
+            }
+        };
+
+        let moves_lost = (self.state().move_count + analysis.optimal_movement_count) as isize
+            - initial_optimal_movement_count as isize;
+
+        Some(Hint {
+            next_move: analysis.routes[0][0].solution[0].0,
+            remaining_moves: analysis.optimal_movement_count as isize,
+            on_optimal_path: moves_lost <= 0,
+            moves_lost: moves_lost.max(0),
+        })
+    }
+
+    pub fn exit_reachable(&self, exit_gate_id: isize) -> bool {
+        exit_reachable(
+            &self.state().board,
+            self.state().position,
+            exit_gate_id as usize,
+        )
+    }
+
+    pub fn at(&self, p: &Pos) -> Tile {
+        self.state().board.at(p)
+    }
+
+    pub fn get_position(&self) -> Pos {
+        self.state().position
+    }
+
+    pub fn get_move_count(&self) -> isize {
+        self.state().move_count as isize
+    }
+}
+
+impl SessionState {
+    fn solve(&self, exit_gate_id: usize, limits: &SolverLimits) -> Option<Analysis> {
+        if self.position == self.board.get_gate_position(exit_gate_id) {
+            return None;
+        }
+
+        analyze_from(&self.board, self.position, Some(vec![exit_gate_id]), limits).ok()
+    }
+
+    fn apply_move(&mut self, direction: Direction) -> MoveOutcome {
+        let start = self.position;
+        let step = step(&self.board.map, &start, &direction);
+
+        let mut path = vec![];
+        let mut cursor = start;
+        let mut teleports = step.teleports.iter();
+        let mut next_teleport = teleports.next();
+        while cursor != step.pos || next_teleport.is_some() {
+            cursor = cursor + direction.vector();
+            path.push(cursor);
+
+            if let Some((_, twin)) = next_teleport.filter(|(portal, _)| *portal == cursor) {
+                cursor = *twin;
+                path.push(cursor);
+                next_teleport = teleports.next();
+            }
+        }
+
+        let mut pushed_boxes = vec![];
+        let mut broken_walls = vec![];
+        let mut picked_keys = vec![];
+        let mut opened_locks = vec![];
+        let mut cracked_ice = vec![];
+
+        if step.changes_board() {
+            for change in Arc::make_mut(&mut self.board).resolve_step(&step, &direction) {
+                match change {
+                    BoardChange::BrokenWall(p) => broken_walls.push(p),
+                    BoardChange::PushedBoxes(moves) => pushed_boxes = moves,
+                    BoardChange::PickedKey {
+                        key,
+                        opened_locks: locks,
+                    } => {
+                        picked_keys.push(key);
+                        opened_locks.extend(locks);
+                    }
+                    BoardChange::CrackedIce(p) => cracked_ice.push(p),
+                    BoardChange::None => {}
+                }
+            }
+        }
+
+        if !path.is_empty() || step.changes_board() {
+            self.move_count += 1;
+        }
+
+        self.position = step.pos;
+
+        MoveOutcome {
+            direction,
+            gate_reached: if path.is_empty() {
+                None
+            } else {
+                self.board
+                    .get_gate_id_by_pos(step.pos)
+                    .map(|id| id as isize)
+            },
+            path,
+            pushed_boxes,
+            broken_walls,
+            picked_keys,
+            opened_locks,
+            teleports: step.teleports,
+            cracked_ice,
+            move_count: self.move_count as isize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        api::{
+            board_description::GameMode,
+            main::{GateDestination, GateMetadata},
+        },
+        logic::solver::analyze,
+    };
+
+    // The entrance is gate 1 at the bottom, the exit gate 0 on the right
+    fn session() -> GameSession {
+        let board = DartBoard::new_lobby(
+            "# # # # # # # \n\
+             #   w       # \n\
+             #           # \n\
+             #     b     A \n\
+             # E # # # # # \n"
+                .to_string(),
+            HashMap::from([(
+                b'A',
+                GateMetadata::Exit {
+                    destination: GateDestination::NextAutoGen,
+                    label: None,
+                },
+            )]),
+            vec![],
+            None,
+        )
+        .unwrap();
+
+        GameSession::new(&board, 1)
+    }
+
+    #[test]
+    fn slides_until_something_is_hit() {
+        let mut session = session();
+        let outcome = session.apply_move(Direction::North);
+
+        assert_eq!(
+            outcome.path,
+            vec![Pos::new(1, 3), Pos::new(1, 2), Pos::new(1, 1)]
+        );
+        assert_eq!(outcome.move_count, 1);
+        assert_eq!(session.get_position(), Pos::new(1, 1));
+    }
+
+    #[test]
+    fn breaks_weak_walls_and_pushes_boxes() {
+        let mut session = session();
+        session.apply_move(Direction::North);
+
+        let outcome = session.apply_move(Direction::East);
+        assert!(outcome.path.is_empty());
+        assert_eq!(outcome.broken_walls, vec![Pos::new(2, 1)]);
+        assert_eq!(session.at(&Pos::new(2, 1)), Tile::Ice);
+
+        session.apply_move(Direction::East);
+        session.apply_move(Direction::South);
+
+        let outcome = session.apply_move(Direction::West);
+        assert_eq!(outcome.path, vec![Pos::new(4, 3)]);
+        assert_eq!(outcome.pushed_boxes, vec![(Pos::new(3, 3), Pos::new(1, 3))]);
+        assert_eq!(session.at(&Pos::new(1, 3)), Tile::Box);
+    }
+
+    #[test]
+    fn reaching_a_gate() {
+        let mut session = session();
+        for direction in [
+            Direction::North,
+            Direction::East,
+            Direction::East,
+            Direction::South,
+            Direction::West,
+        ] {
+            assert_eq!(session.apply_move(direction).gate_reached, None);
+        }
+
+        let outcome = session.apply_move(Direction::East);
+        assert_eq!(outcome.gate_reached, Some(0));
+        assert_eq!(outcome.move_count, 6);
+    }
+
+    #[test]
+    fn bumping_into_a_wall_is_not_a_move() {
+        let mut session = session();
+        session.apply_move(Direction::North);
+
+        let outcome = session.apply_move(Direction::West);
+        assert!(outcome.path.is_empty());
+        assert_eq!(outcome.move_count, 1);
+        assert_eq!(session.get_move_count(), 1);
+    }
+
+    #[test]
+    fn undo_redo_and_reset() {
+        let mut session = session();
+        let north = session.apply_move(Direction::North);
+        let east = session.apply_move(Direction::East);
+
+        assert_eq!(session.undo(), Some(east.clone()));
+        assert_eq!(session.at(&Pos::new(2, 1)), Tile::WeakWall);
+        assert_eq!(session.get_move_count(), 1);
+        assert!(session.can_redo());
+
+        assert_eq!(session.redo(), Some(east.clone()));
+        assert_eq!(session.at(&Pos::new(2, 1)), Tile::Ice);
+        assert_eq!(session.redo(), None);
+        assert_eq!(session.get_move_history(), vec![north.clone(), east]);
+
+        session.reset();
+        assert_eq!(session.get_position(), Pos::new(1, 4));
+        assert_eq!(session.get_move_count(), 0);
+        assert!(!session.can_undo());
+        assert!(!session.can_redo());
+        assert_eq!(session.undo(), None);
+    }
+
+    #[test]
+    fn a_new_move_drops_the_redo_history() {
+        let mut session = session();
+        let north = session.apply_move(Direction::North);
+        session.apply_move(Direction::East);
+        session.undo();
+
+        let south = session.apply_move(Direction::South);
+
+        assert!(!session.can_redo());
+        assert_eq!(session.get_move_history(), vec![north, south]);
+    }
+
+    #[test]
+    fn wall_bumps_stay_out_of_the_history() {
+        let mut session = session();
+        session.apply_move(Direction::North);
+        session.apply_move(Direction::West);
+
+        assert_eq!(session.get_move_history().len(), 1);
+    }
+
+    // Generated rooms are analyzed from gate 0 to gate 1 only, gate 2 is the
+    // other way out. Gate 1 is 13 moves away, past the default depth.
+    fn deep_limits() -> SolverLimits {
+        SolverLimits {
+            max_solution_depth: 16,
+            near_optimal_tiers: 2,
+        }
+    }
+
+    fn analyzed_session(limits: SolverLimits) -> GameSession {
+        let exit = || GateMetadata::Exit {
+            destination: GateDestination::NextAutoGen,
+            label: None,
+        };
+        let lobby = DartBoard::new_lobby(
+            "# # E # # # # \n\
+             #           # \n\
+             #     #     A \n\
+             #   b w     # \n\
+             # # # # B # # \n"
+                .to_string(),
+            HashMap::from([(b'A', exit()), (b'B', exit())]),
+            vec![],
+            None,
+        )
+        .unwrap();
+        let analysis = analyze(&lobby.board, 0, 1, &deep_limits()).unwrap();
+        let board = DartBoard::new(
+            lobby.board.clone(),
+            Some((analysis, GameMode::FindExit)),
+            None,
+            limits,
+            0,
+        );
+
+        GameSession::new(&board, 0)
+    }
+
+    #[test]
+    fn hints_count_lost_moves_per_exit() {
+        let mut session = analyzed_session(deep_limits());
+
+        for exit_gate_id in [1, 2] {
+            let hint = session.hint(exit_gate_id).unwrap();
+            assert_eq!(hint.moves_lost, 0, "exit {exit_gate_id}");
+            assert!(hint.on_optimal_path);
+        }
+
+        let to_gate_2 = session.hint(2).unwrap();
+        session.apply_move(to_gate_2.next_move);
+
+        let hint = session.hint(2).unwrap();
+        assert_eq!(hint.moves_lost, 0);
+        assert_eq!(hint.remaining_moves, to_gate_2.remaining_moves - 1);
+
+        // Gate 2 is much closer than gate 1, measuring against gate 1's
+        // optimum would hide the wasted move
+        session.apply_move(Direction::West);
+
+        let hint = session.hint(2).unwrap();
+        assert_eq!(hint.moves_lost, 1);
+        assert!(!hint.on_optimal_path);
+    }
+
+    #[test]
+    fn hints_search_within_the_room_limits() {
+        let session = analyzed_session(SolverLimits {
+            max_solution_depth: 13,
+            near_optimal_tiers: 0,
+        });
+
+        assert!(session.hint(1).is_some());
+        assert_eq!(session.hint(2).map(|hint| hint.remaining_moves), Some(6));
+
+        let session = analyzed_session(SolverLimits {
+            max_solution_depth: 12,
+            near_optimal_tiers: 0,
+        });
+
+        assert_eq!(session.hint(1), None);
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use crate::{
+    api::{
+        board_description::BoardDescription,
+        direction::Direction,
+        main::{AutoGenOutput, BestEffortOutput},
+    },
+    frb_generated::StreamSink,
+    logic::worker_pool::WorkerPool,
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GeneratorConfig {
+    pub thread_count: Option<isize>,
+    pub duty_cycle: f64,
+    pub time_budget_millis: Option<isize>,
+    pub stagnation_limit: Option<isize>,
+    pub buffer_dir: Option<String>,
+    // The contents of a level pack, not its path
+    pub level_pack: Option<Vec<u8>>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            thread_count: None,
+            duty_cycle: 1.,
+            time_budget_millis: None,
+            stagnation_limit: None,
+            buffer_dir: None,
+            level_pack: None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum GeneratorState {
+    Running,
+    Paused,
+    Finished,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GeneratorStatus {
+    pub state: GeneratorState,
+    pub iterations_per_worker: Vec<isize>,
+    pub candidates_tried: isize,
+    pub solvable_rate: f64,
+    pub fitness_threshold: f64,
+    pub buffered_boards: isize,
+    pub remaining_boards: isize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum GeneratorEvent {
+    BoardAccepted {
+        fitness: f64,
+        buffered_boards: isize,
+    },
+    FitnessThresholdImproved {
+        fitness_threshold: f64,
+    },
+    Finished,
+}
+
+// Dropping the handle stops its workers, so the Dart side only has to keep it
+// around for as long as it wants rooms from it. With a buffer_dir the unserved
+// boards are kept on disk as they come and go, and picked up by the next
+// handle created for the same description.
+pub struct GeneratorHandle {
+    pool: WorkerPool,
+}
+
+impl GeneratorHandle {
+    pub fn new(
+        board_desc: BoardDescription,
+        max_buffered_boards: isize,
+        seed: Option<u64>,
+        config: GeneratorConfig,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            pool: WorkerPool::start(board_desc, max_buffered_boards, seed, config)?,
+        })
+    }
+
+    pub fn get_new_board(&self, entry_direction: Direction) -> AutoGenOutput {
+        self.pool.get_new_room(entry_direction)
+    }
+
+    pub fn get_new_board_within(
+        &self,
+        entry_direction: Direction,
+        timeout_millis: isize,
+    ) -> BestEffortOutput {
+        self.pool.get_new_room_within(
+            entry_direction,
+            Duration::from_millis(timeout_millis.max(0) as u64),
+        )
+    }
+
+    pub fn halt(&self, millis: usize) {
+        self.pool.halt(millis)
+    }
+
+    pub fn pause(&self) {
+        self.pool.pause()
+    }
+
+    pub fn resume(&self) {
+        self.pool.resume()
+    }
+
+    pub fn shutdown(&self) -> Result<(), String> {
+        self.pool.shutdown()
+    }
+
+    pub fn persist(&self) -> Result<(), String> {
+        self.pool.persist()
+    }
+
+    pub fn subscribe(&self, sink: StreamSink<GeneratorEvent>) {
+        self.pool.subscribe(sink)
+    }
+
+    pub fn status(&self) -> GeneratorStatus {
+        self.pool.status()
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    api::{main::GateMetadata, pos::Pos, tile::Tile},
+    logic::{board::Board, gate::GateEntry, matrix::Matrix},
+};
+
+// Exit gates get a key of their own, lowercase letters and punctuation are
+// taken by tiles and 'S' by signs
+const EXIT_GATE_KEYS: &[u8] = b"ABCDFGHIJKLMNOPQRTUVWXYZ0123456789";
+const ENTRY_ONLY_GATE_KEY: u8 = b'E';
+
+// A board in the format DartBoard::new_lobby reads, everything a tile symbol
+// can't hold goes in the sidecar fields
+#[derive(Clone, PartialEq, Debug)]
+pub struct LobbyText {
+    pub serialized: String,
+    pub gate_metadata: HashMap<u8, GateMetadata>,
+    pub sign_text: Vec<(String, isize, isize)>,
+    // Lobbies number their gates in reading order, this is the id each of
+    // them had on the board
+    pub gate_ids: Vec<isize>,
+}
+
+impl LobbyText {
+    pub(crate) fn new(board: &Board) -> Result<Self, String> {
+        let mut ret = Self {
+            serialized: String::new(),
+            gate_metadata: HashMap::new(),
+            sign_text: vec![],
+            gate_ids: vec![],
+        };
+        let mut exit_gate_keys = EXIT_GATE_KEYS.iter();
+
+        for (y, row) in board.map.0.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let symbol = match tile {
+                    Tile::Gate(metadata) => {
+                        let pos = Pos::new(x as isize, y as isize);
+                        let id = board.get_gate_id_by_pos(pos).ok_or(format!(
+                            "gate tile at {pos:?} is not one of the board's gates"
+                        ))?;
+
+                        ret.gate_ids.push(id as isize);
+
+                        if let GateMetadata::EntryOnly = metadata {
+                            ENTRY_ONLY_GATE_KEY
+                        } else {
+                            let key = *exit_gate_keys
+                                .next()
+                                .ok_or("the board has more exit gates than gate keys")?;
+
+                            ret.gate_metadata.insert(key, metadata.clone());
+                            key
+                        }
+                    }
+                    Tile::Sign {
+                        text,
+                        width,
+                        height,
+                    } => {
+                        ret.sign_text.push((text.clone(), *width, *height));
+                        b'S'
+                    }
+                    tile => tile.symbol().as_bytes()[0],
+                };
+
+                ret.serialized.push(symbol as char);
+                ret.serialized.push(match tile.symbol_id() {
+                    Some(id @ 0..=9) => (b'0' + id as u8) as char,
+                    Some(id) => {
+                        return Err(format!(
+                            "{} at {x}, {y} has id {id}, the lobby format only holds 0 to 9",
+                            tile.symbol()
+                        ))
+                    }
+                    None => ' ',
+                });
+            }
+
+            ret.serialized.push('\n');
+        }
+
+        if ret.gate_ids.len() != board.gates.len() {
+            return Err("some of the board's gates are not gate tiles".to_string());
+        }
+
+        Ok(ret)
+    }
+
+    pub(crate) fn to_board(&self) -> Result<Board, String> {
+        let board = parse_lobby(
+            &self.serialized,
+            &self.gate_metadata,
+            self.sign_text.clone(),
+        )?;
+
+        let mut gates = board.gates.clone();
+        let mut seen = vec![false; gates.len()];
+
+        if self.gate_ids.len() != gates.len() {
+            return Err(format!(
+                "{} gate ids for {} gates",
+                self.gate_ids.len(),
+                gates.len()
+            ));
+        }
+
+        for (gate, id) in board.gates.iter().zip(&self.gate_ids) {
+            match seen.get_mut(*id as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return Err(format!("gate id {id} is out of range or repeated")),
+            }
+
+            gates[*id as usize] = gate.clone();
+        }
+
+        Ok(Board {
+            map: board.map,
+            gates,
+        })
+    }
+}
+
+// Two characters per tile, a symbol and a space, or a digit for the id of a
+// key or a keyed lock. Gates are numbered in reading order and signs take
+// their text in that order too. Every problem found is reported, one per
+// line, with the line and column it was found at.
+pub(crate) fn parse_lobby(
+    serialized: &str,
+    gate_metadata: &HashMap<u8, GateMetadata>,
+    sign_text: Vec<(String, isize, isize)>,
+) -> Result<Board, String> {
+    let mut errors = vec![];
+    let mut map: Vec<Vec<Tile>> = vec![];
+    let mut row_lines = vec![];
+    let mut used_keys = HashSet::new();
+    let mut sign_text = sign_text.into_iter();
+
+    for (line_id, line) in serialized.split('\n').enumerate() {
+        let line = line.as_bytes();
+        let line_number = line_id + 1;
+        let mut row = vec![];
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.len() % 2 != 0 {
+            errors.push(format!(
+                "line {line_number}, column {}: odd number of characters, every tile is a symbol followed by a space",
+                line.len()
+            ));
+        }
+
+        for (x, pair) in line.chunks(2).enumerate() {
+            let column = 2 * x + 1;
+
+            let id = match pair.get(1) {
+                None | Some(b' ') => None,
+                Some(c) if c.is_ascii_digit() => Some((c - b'0') as isize),
+                Some(_) => {
+                    errors.push(format!(
+                        "line {line_number}, column {}: expected a space or an id after {:?}",
+                        column + 1,
+                        pair[0] as char
+                    ));
+                    None
+                }
+            };
+
+            match Tile::from_symbol(pair[0], id, gate_metadata, &mut sign_text) {
+                Ok(tile) => {
+                    if let Tile::Gate(_) = tile {
+                        used_keys.insert(pair[0]);
+                    }
+                    row.push(tile);
+                }
+                Err(e) => {
+                    errors.push(format!("line {line_number}, column {column}: {e}"));
+                    row.push(Tile::Outside);
+                }
+            }
+        }
+
+        if let Some(first_row) = map.first() {
+            if first_row.len() != row.len() {
+                errors.push(format!(
+                    "line {line_number}: row has {} tiles, the first row on line {} has {}",
+                    row.len(),
+                    row_lines[0],
+                    first_row.len()
+                ));
+            }
+        }
+
+        map.push(row);
+        row_lines.push(line_number);
+    }
+
+    if map.is_empty() {
+        errors.push("the lobby has no rows".to_string());
+    }
+
+    let mut unused_keys = gate_metadata
+        .keys()
+        .filter(|key| !used_keys.contains(*key))
+        .map(|key| format!("{:?}", *key as char))
+        .collect::<Vec<_>>();
+    unused_keys.sort();
+
+    if !unused_keys.is_empty() {
+        errors.push(format!(
+            "no tile uses the gate metadata for {}",
+            unused_keys.join(", ")
+        ));
+    }
+
+    let unused_signs = sign_text.count();
+    if unused_signs != 0 {
+        errors.push(format!(
+            "there are {unused_signs} more sign texts than signs"
+        ));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let width = map[0].len();
+    let height = map.len();
+    let mut gates = vec![];
+
+    for (y, row) in map.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            if let Tile::Gate(_) = tile {
+                if x != 0 && y != 0 && x != width - 1 && y != height - 1 {
+                    errors.push(format!(
+                        "line {}, column {}: gates have to be on the border of the room",
+                        row_lines[y],
+                        2 * x + 1
+                    ));
+                }
+
+                gates.push(GateEntry::new(
+                    Pos::new(x as isize, y as isize),
+                    width as isize,
+                ));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    Ok(Board {
+        map: Matrix(map),
+        gates,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::api::{
+        board_description::BoardDescription,
+        direction::Direction,
+        main::{GateDestination, RoomIdAndGate},
+    };
+
+    fn exit_gate() -> GateMetadata {
+        GateMetadata::Exit {
+            destination: GateDestination::NextAutoGen,
+            label: None,
+        }
+    }
+
+    fn gate_positions(board: &Board) -> Vec<(Pos, Direction)> {
+        board
+            .gates
+            .iter()
+            .map(|gate| (gate.pos, gate.inwards_direction))
+            .collect()
+    }
+
+    fn assert_round_trips(board: &Board) {
+        let text = LobbyText::new(board).unwrap();
+        let back = text.to_board().unwrap();
+
+        assert_eq!(back.map, board.map);
+        assert_eq!(gate_positions(&back), gate_positions(board));
+    }
+
+    fn parse_error(serialized: &str, gate_metadata: HashMap<u8, GateMetadata>) -> String {
+        parse_lobby(serialized, &gate_metadata, vec![]).unwrap_err()
+    }
+
+    #[test]
+    fn every_tile_round_trips() {
+        let labeled_exit = GateMetadata::Exit {
+            destination: GateDestination::RoomIdWithGate(RoomIdAndGate {
+                room_id: "lobby".to_string(),
+                gate_id: 1,
+            }),
+            label: Some("1".to_string()),
+        };
+        let board = parse_lobby(
+            "# # A # # # # \n\
+             #   w b s c # \n\
+             # ^ > v < S # \n\
+             # k3l3l p1p1# \n\
+             # - - - - - G \n\
+             # # E # # # # \n",
+            &HashMap::from([(b'A', labeled_exit), (b'G', exit_gate())]),
+            vec![("hello".to_string(), 2, 1)],
+        )
+        .unwrap();
+
+        assert_round_trips(&board);
+    }
+
+    #[test]
+    fn generated_boards_round_trip() {
+        let desc = BoardDescription {
+            key_lock_pairs_max: 2,
+            portal_percentage_max: 5,
+            arrow_percentage_max: 5,
+            stop_percentage_max: 5,
+            cracked_ice_percentage_max: 5,
+            weak_walls_percentage_max: 5,
+            box_percentage_max: 5,
+            ..BoardDescription::default()
+        };
+
+        for seed in 0..10 {
+            if let Ok(board) = Board::new_random(&desc, &mut StdRng::seed_from_u64(seed)) {
+                assert_round_trips(&board);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_length_line() {
+        let e = parse_error(
+            "# G # \n#   #\n# E # \n",
+            HashMap::from([(b'G', exit_gate())]),
+        );
+
+        assert!(
+            e.contains("line 2, column 5: odd number of characters"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let e = parse_error(
+            "# G # \n#   \n# E # \n",
+            HashMap::from([(b'G', exit_gate())]),
+        );
+
+        assert!(
+            e.contains("line 2: row has 2 tiles, the first row on line 1 has 3"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn gate_not_on_the_border() {
+        let e = parse_error(
+            "# # # # \n# G   # \n# E # # \n",
+            HashMap::from([(b'G', exit_gate())]),
+        );
+
+        assert!(
+            e.contains("line 2, column 3: gates have to be on the border of the room"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn unused_metadata_key() {
+        let e = parse_error(
+            "# G # \n#   # \n# E # \n",
+            HashMap::from([(b'G', exit_gate()), (b'Z', exit_gate())]),
+        );
+
+        assert!(e.contains("no tile uses the gate metadata for 'Z'"), "{e}");
+    }
+}
@@ -1,7 +1,4 @@
-use crate::{
-    api::{board_description::BoardDescription, dart_board::DartBoard, direction::Direction},
-    logic::worker_pool::{get_new_room, halt_search, start_search, stop_search},
-};
+use crate::api::{board_description::BoardDescription, dart_board::DartBoard};
 
 pub trait LeftRotatable {
     fn rotate_left(&self) -> Self;
@@ -24,8 +21,9 @@ pub struct RoomIdAndGate {
 #[derive(Clone, PartialEq, Debug, Eq, Hash)]
 pub enum GateDestination {
     NextAutoGen,
+    // Boxed so every gate tile doesn't carry a whole description around
     FirstAutogen {
-        board_description: BoardDescription,
+        board_description: Box<BoardDescription>,
         board_count: isize,
         end_of_game_metadata: EndOfGameMetadata,
     },
@@ -58,21 +56,13 @@ pub enum AutoGenOutput {
     NoMoreBufferedBoards,
 }
 
-pub fn dart_start_search(board_desc: BoardDescription, max_buffered_boards: isize) {
-    print!("start search");
-    start_search(board_desc, max_buffered_boards);
-}
-
-pub fn dart_get_new_board(entry_direction: Direction) -> AutoGenOutput {
-    get_new_room(entry_direction)
-}
-
-pub fn dart_worker_halt(millis: usize) {
-    halt_search(millis)
-}
-
-pub fn dart_stop_search() {
-    stop_search()
+#[frb(non_opaque)]
+pub enum BestEffortOutput {
+    Buffered(DartBoard),
+    BelowThreshold(DartBoard),
+    Fallback(DartBoard),
+    NotReady,
+    NoMoreBufferedBoards,
 }
 
 // use cap::Cap;
@@ -5,3 +5,7 @@ pub mod pos;
 pub mod dart_board;
 pub mod asset_map;
 pub mod board_description;
+pub mod game_session;
+pub mod difficulty;
+pub mod generator;
+pub mod lobby_text;
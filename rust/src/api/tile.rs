@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use crate::api::main::{GateMetadata, LeftRotatable};
+use crate::api::{
+    direction::Direction,
+    main::{GateMetadata, LeftRotatable},
+};
 
 #[derive(Clone, PartialEq, Debug, Eq, Hash)]
 pub enum Tile {
@@ -11,7 +14,23 @@ pub enum Tile {
     WeakWall,
     Box,
     Outside,
-    Lock,
+    // Lobby locks have no key and never open, keyed ones open once the
+    // player picks up the key with the same id
+    Lock {
+        key: Option<isize>,
+    },
+    Key {
+        id: isize,
+    },
+    // Sends the player to the other portal with the same id, where they keep
+    // sliding the same way
+    Portal {
+        id: isize,
+    },
+    // Stops the player like Stop, but the next move has to be the arrow's
+    Arrow(Direction),
+    // Turns into a wall once the player has gone over it and left it
+    CrackedIce,
     Sign {
         text: String,
         width: isize,
@@ -23,6 +42,7 @@ impl LeftRotatable for Tile {
     fn rotate_left(&self) -> Self {
         match self {
             Tile::Gate(metadata) => Tile::Gate(metadata.clone()),
+            Tile::Arrow(direction) => Tile::Arrow(direction.left()),
 
             Tile::Sign {
                 text,
@@ -53,9 +73,13 @@ impl Tile {
             Tile::Stop => "s",
             Tile::Ice => " ",
             Tile::WeakWall => "w",
-            Tile::Outside => " ",
+            Tile::Outside => "-",
             Tile::Box => "b",
-            Tile::Lock => "l",
+            Tile::Lock { .. } => "l",
+            Tile::Key { .. } => "k",
+            Tile::Portal { .. } => "p",
+            Tile::Arrow(direction) => direction.icon(),
+            Tile::CrackedIce => "c",
             Tile::Sign { .. } => "S",
         }
     }
@@ -69,7 +93,11 @@ impl Tile {
             Tile::Ice => false,
             Tile::WeakWall => true,
             Tile::Outside => true,
-            Tile::Lock => true,
+            Tile::Lock { .. } => true,
+            Tile::Key { .. } => false,
+            Tile::Portal { .. } => false,
+            Tile::Arrow(_) => false,
+            Tile::CrackedIce => false,
             Tile::Box => true,
             Tile::Sign { .. } => false,
         }
@@ -84,7 +112,11 @@ impl Tile {
             Tile::Ice => false,
             Tile::WeakWall => false,
             Tile::Outside => true,
-            Tile::Lock => true,
+            Tile::Lock { .. } => true,
+            Tile::Key { .. } => false,
+            Tile::Portal { .. } => false,
+            Tile::Arrow(_) => false,
+            Tile::CrackedIce => false,
             Tile::Box => false,
             Tile::Sign { .. } => false,
         }
@@ -99,7 +131,11 @@ impl Tile {
             Tile::Ice => false,
             Tile::WeakWall => false,
             Tile::Outside => true,
-            Tile::Lock => false,
+            Tile::Lock { .. } => false,
+            Tile::Key { .. } => false,
+            Tile::Portal { .. } => false,
+            Tile::Arrow(_) => false,
+            Tile::CrackedIce => false,
             Tile::Box => false,
             Tile::Sign { .. } => false,
         }
@@ -114,35 +150,88 @@ impl Tile {
             Tile::WeakWall => false,
             Tile::Outside => true,
             Tile::Box => false,
-            Tile::Lock => true,
+            Tile::Lock { .. } => true,
+            Tile::Key { .. } => true,
+            Tile::Portal { .. } => true,
+            Tile::Arrow(_) => true,
+            Tile::CrackedIce => true,
             Tile::Sign { .. } => false,
         }
     }
 
+    pub fn changes_board_on_hit(&self) -> bool {
+        matches!(self, Tile::WeakWall | Tile::Box)
+    }
+
+    pub fn changes_board_on_pass(&self) -> bool {
+        matches!(self, Tile::Key { .. } | Tile::CrackedIce)
+    }
+
+    // The id keys, keyed locks and portals carry in the second character of their
+    // lobby symbol
+    pub(crate) fn symbol_id(&self) -> Option<isize> {
+        match self {
+            Tile::Key { id } | Tile::Portal { id } => Some(*id),
+            Tile::Lock { key } => *key,
+            _ => None,
+        }
+    }
+
+    // 'E' is an entry only gate unless the metadata says otherwise, any other
+    // gate needs metadata. Only keys, locks and portals take an id.
     pub(crate) fn from_symbol(
         symbol: u8,
+        id: Option<isize>,
         gate_metadata: &HashMap<u8, GateMetadata>,
-        sign_metadata: &mut Vec<(String, isize, isize)>,
-    ) -> Tile {
-        match symbol {
+        sign_metadata: &mut impl Iterator<Item = (String, isize, isize)>,
+    ) -> Result<Tile, String> {
+        match (symbol, id) {
+            (b'l', key) => return Ok(Tile::Lock { key }),
+            (b'k', Some(id)) => return Ok(Tile::Key { id }),
+            (b'k', None) => return Err("keys need an id after the 'k'".to_string()),
+            (b'p', Some(id)) => return Ok(Tile::Portal { id }),
+            (b'p', None) => return Err("portals need an id after the 'p'".to_string()),
+            (symbol, Some(id)) => {
+                return Err(format!(
+                    "{:?} doesn't take an id, found {id}",
+                    symbol as char
+                ))
+            }
+            _ => {}
+        }
+
+        Ok(match symbol {
             b'#' => Tile::Wall,
             b' ' => Tile::Ice,
+            b'-' => Tile::Outside,
             b'w' => Tile::WeakWall,
             b'b' => Tile::Box,
             b's' => Tile::Stop,
-            b'l' => Tile::Lock,
+            b'c' => Tile::CrackedIce,
+            b'^' => Tile::Arrow(Direction::North),
+            b'>' => Tile::Arrow(Direction::East),
+            b'v' => Tile::Arrow(Direction::South),
+            b'<' => Tile::Arrow(Direction::West),
             b'S' => {
-                let metadata = sign_metadata.remove(0);
+                let metadata = sign_metadata
+                    .next()
+                    .ok_or("there are more signs than sign texts")?;
                 Tile::Sign {
                     text: metadata.0,
                     width: metadata.1,
                     height: metadata.2,
                 }
             }
-            e => {
-                let metadata = gate_metadata.get(&e).cloned();
-                Tile::Gate(metadata.unwrap_or(GateMetadata::EntryOnly))
-            }
-        }
+            e => match gate_metadata.get(&e) {
+                Some(metadata) => Tile::Gate(metadata.clone()),
+                None if e == b'E' => Tile::Gate(GateMetadata::EntryOnly),
+                None => {
+                    return Err(format!(
+                        "unknown symbol {:?}, gates other than 'E' need gate metadata",
+                        e as char
+                    ))
+                }
+            },
+        })
     }
 }
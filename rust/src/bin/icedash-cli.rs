@@ -0,0 +1,206 @@
+//! Generates and inspects rooms without running the app, and can store them in
+//! a level pack for the app to serve. The app ships the pack at
+//! `assets/packs/levels.pack`.
+//!
+//! `cargo run --features cli --bin icedash-cli -- --desc <description> [options]`
+
+use std::{collections::HashMap, fs, path::Path, thread::sleep, time::Duration};
+
+use rust_lib_icedash::{
+    api::{
+        board_description::BoardDescription,
+        generator::{GeneratorConfig, GeneratorState},
+    },
+    logic::{level_pack::LevelPack, worker_pool::WorkerPool},
+};
+
+const USAGE: &str = "usage: icedash-cli --desc <json | list | file> [options]
+
+  --desc <value>       board description, inline or in a file. Either a JSON
+                       object with the BoardDescription field names, where
+                       fields left out take BoardDescription::default() and
+                       game_mode is \"FindExit\" or \"FindPerfectPath\", or
+                       the comma separated numbers of BoardDescription::as_list
+  --seed <n>           seed for the workers, random if left out
+  --count <n>          number of boards to generate (default 10)
+  --threads <n>        worker threads (default 1, so a seed reproduces its boards)
+  --stagnation <n>     iterations without a better board once --count boards
+                       are found before stopping (default 500)
+  --budget-ms <n>      time limit for the whole run
+  --pack <path>        add the boards to this level pack, creating it if needed";
+
+fn main() {
+    if let Err(e) = run(std::env::args().skip(1).collect()) {
+        eprintln!("{e}\n\n{USAGE}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut options = HashMap::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument {arg}"));
+        };
+
+        if !matches!(
+            name,
+            "desc" | "seed" | "count" | "threads" | "stagnation" | "budget-ms" | "pack"
+        ) {
+            return Err(format!("unknown option {arg}"));
+        }
+
+        let value = args.next().ok_or(format!("{arg} needs a value"))?;
+        options.insert(name.to_string(), value);
+    }
+
+    let number = |name: &str, default: Option<isize>| -> Result<Option<isize>, String> {
+        match options.get(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("--{name} expects a number, got {value}")),
+            None => Ok(default),
+        }
+    };
+
+    let desc = options.get("desc").ok_or("--desc is required")?;
+    let board_desc = parse_description(&match Path::new(desc).is_file() {
+        true => fs::read_to_string(desc).map_err(|e| format!("couldnt read {desc}: {e}"))?,
+        false => desc.clone(),
+    })?;
+
+    let seed = match options.get("seed") {
+        Some(seed) => Some(
+            seed.parse::<u64>()
+                .map_err(|_| format!("--seed expects a number, got {seed}"))?,
+        ),
+        None => None,
+    };
+    let count = number("count", Some(10))?.unwrap();
+
+    let pool = WorkerPool::start(
+        board_desc.clone(),
+        count,
+        seed,
+        GeneratorConfig {
+            thread_count: number("threads", Some(1))?,
+            stagnation_limit: number("stagnation", Some(500))?,
+            time_budget_millis: number("budget-ms", None)?,
+            ..GeneratorConfig::default()
+        },
+    )?;
+
+    while pool.status().state != GeneratorState::Finished {
+        sleep(Duration::from_millis(100));
+    }
+
+    let boards = pool.drain();
+
+    for (i, packed) in boards.iter().enumerate() {
+        println!(
+            "\nboard {}/{} seed {} fitness {}",
+            i + 1,
+            boards.len(),
+            packed.seed,
+            packed.fitness
+        );
+        packed.board.map.print(
+            packed.analysis.routes[0][0]
+                .solution
+                .iter()
+                .map(|e| e.1)
+                .collect(),
+        );
+        packed.analysis.print();
+    }
+
+    if (boards.len() as isize) < count {
+        eprintln!("only found {} of {count} boards", boards.len());
+    }
+
+    if let Some(path) = options.get("pack") {
+        let mut pack = match Path::new(path).exists() {
+            true => LevelPack::read(path)?,
+            false => LevelPack::default(),
+        };
+
+        for packed in boards {
+            pack.push(&board_desc, packed);
+        }
+
+        pack.write(path)?;
+        println!("wrote {path}");
+    }
+
+    Ok(())
+}
+
+fn parse_description(text: &str) -> Result<BoardDescription, String> {
+    let text = text.trim();
+
+    if text.starts_with('{') {
+        return serde_json::from_str(text).map_err(|e| format!("invalid description: {e}"));
+    }
+
+    let list = text
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|e| {
+            e.trim()
+                .parse()
+                .map_err(|_| format!("{:?} is not a number", e.trim()))
+        })
+        .collect::<Result<Vec<isize>, _>>()?;
+
+    let expected = BoardDescription::default().as_list().len();
+
+    if list.len() != expected {
+        return Err(format!(
+            "description has {} values, expected {expected}",
+            list.len()
+        ));
+    }
+
+    Ok(BoardDescription::from_list(list))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_lib_icedash::api::board_description::GameMode;
+
+    use super::*;
+
+    #[test]
+    fn parses_json_and_list_descriptions() {
+        let desc = BoardDescription {
+            area: 64,
+            game_mode: GameMode::FindPerfectPath,
+            stop_percentage_max: 7,
+            ..BoardDescription::default()
+        };
+
+        let json = parse_description(
+            r#"{ "area": 64, "game_mode": "FindPerfectPath", "stop_percentage_max": 7 }"#,
+        )
+        .unwrap();
+        assert_eq!(json, desc);
+
+        let list = desc
+            .as_list()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        assert_eq!(parse_description(&list).unwrap(), desc);
+        assert_eq!(parse_description(&format!("[{list}]\n")).unwrap(), desc);
+
+        let e = parse_description("49, 0, 5").unwrap_err();
+        assert_eq!(e, "description has 3 values, expected 35");
+        let e = parse_description("49, x").unwrap_err();
+        assert_eq!(e, "\"x\" is not a number");
+    }
+}
@@ -1,3 +1,3 @@
 pub mod api;
 mod frb_generated;
-mod logic;
+pub mod logic;
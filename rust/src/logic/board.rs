@@ -1,6 +1,6 @@
 use std::ops::{Deref, DerefMut};
 
-use rand::{random, seq::IteratorRandom, Rng};
+use rand::{seq::IteratorRandom, Rng};
 
 use crate::{
     api::{
@@ -14,9 +14,18 @@ use crate::{
         gate::GateEntry,
         matrix::{Matrix, TileMap},
         noise_reduction::asthetic_filter,
+        solver::StepResult,
     },
 };
 
+pub enum BoardChange {
+    None,
+    BrokenWall(Pos),
+    PushedBoxes(Vec<(Pos, Pos)>),
+    PickedKey { key: Pos, opened_locks: Vec<Pos> },
+    CrackedIce(Pos),
+}
+
 #[derive(Clone, Debug)]
 pub struct Board {
     pub map: TileMap,
@@ -84,6 +93,89 @@ impl Board {
         self.gates.iter().position(|gate| gate.pos == p)
     }
 
+    // Everything a move does to the board, the tiles passed over first and
+    // then the one hit
+    pub fn resolve_step(&mut self, step: &StepResult, direction: &Direction) -> Vec<BoardChange> {
+        let mut ret = step
+            .passed
+            .iter()
+            .map(|p| self.resolve_pass(p))
+            .collect::<Vec<_>>();
+
+        if step.hit.changes_board_on_hit() {
+            ret.push(self.resolve_hit(&step.hit_pos, direction));
+        }
+
+        ret
+    }
+
+    pub fn resolve_pass(&mut self, pos: &Pos) -> BoardChange {
+        match self.map.at(pos) {
+            Tile::Key { id } => {
+                self.map.set(pos, Tile::Ice);
+
+                let opened_locks = self
+                    .map
+                    .all_pos()
+                    .filter(|p| self.map.at(p) == Tile::Lock { key: Some(id) })
+                    .collect::<Vec<_>>();
+
+                for lock in &opened_locks {
+                    self.map.set(lock, Tile::Ice);
+                }
+
+                BoardChange::PickedKey {
+                    key: *pos,
+                    opened_locks,
+                }
+            }
+            Tile::CrackedIce => {
+                self.map.set(pos, Tile::Wall);
+                BoardChange::CrackedIce(*pos)
+            }
+            _ => BoardChange::None,
+        }
+    }
+
+    pub fn resolve_hit(&mut self, hit_pos: &Pos, direction: &Direction) -> BoardChange {
+        match self.map.at(hit_pos) {
+            Tile::WeakWall => {
+                self.map.set(hit_pos, Tile::Ice);
+                BoardChange::BrokenWall(*hit_pos)
+            }
+            Tile::Box => {
+                let before = self.box_line(hit_pos, direction);
+                self.box_cascade(hit_pos, direction);
+                let after = self.box_line(hit_pos, direction);
+
+                BoardChange::PushedBoxes(
+                    before
+                        .into_iter()
+                        .zip(after)
+                        .filter(|(from, to)| from != to)
+                        .collect(),
+                )
+            }
+            _ => BoardChange::None,
+        }
+    }
+
+    // Boxes can only slide over ice and never overtake each other, so the boxes
+    // on this line keep their order through a cascade.
+    fn box_line(&self, start: &Pos, direction: &Direction) -> Vec<Pos> {
+        let mut ret = vec![];
+        let mut cursor = *start;
+
+        loop {
+            match self.map.at(&cursor) {
+                Tile::Box => ret.push(cursor),
+                Tile::Ice => {}
+                _ => return ret,
+            }
+            cursor = cursor + direction.vector();
+        }
+    }
+
     pub fn box_cascade(&mut self, moved_ice_cube: &Pos, direction: &Direction) {
         assert!(self.map.at(moved_ice_cube) == Tile::Box);
 
@@ -100,8 +192,7 @@ impl Board {
         }
     }
 
-    pub fn new_random(desc: &BoardDescription) -> Result<Self, String> {
-        let mut rng = rand::rng();
+    pub fn new_random(desc: &BoardDescription, rng: &mut impl Rng) -> Result<Self, String> {
         let width = (desc.area.isqrt() as i32 + rng.random_range(-2..=2)).max(7) as isize;
         let height =  (desc.area.isqrt() as i32 + rng.random_range(-2..=2)).max(7) as isize;
 
@@ -111,22 +202,22 @@ impl Board {
         let gate_range_vertical = &(3..width - 3);
 
         let (start, start_direction) = (
-            Pos::new(0, gate_range_horizontal.clone().choose(&mut rng).unwrap()),
+            Pos::new(0, gate_range_horizontal.clone().choose(rng).unwrap()),
             Direction::East,
         );
-        let (end, end_direction) = match (1..3).choose(&mut rng).unwrap() {
+        let (end, end_direction) = match (1..3).choose(rng).unwrap() {
             0 => (
-                Pos::new(0, gate_range_horizontal.clone().choose(&mut rng).unwrap()),
+                Pos::new(0, gate_range_horizontal.clone().choose(rng).unwrap()),
                 Direction::East,
             ),
 
             1 => (
-                Pos::new(gate_range_vertical.clone().choose(&mut rng).unwrap(), 0),
+                Pos::new(gate_range_vertical.clone().choose(rng).unwrap(), 0),
                 Direction::South,
             ),
             _ => (
                 Pos::new(
-                    gate_range_vertical.clone().choose(&mut rng).unwrap(),
+                    gate_range_vertical.clone().choose(rng).unwrap(),
                     height - 1,
                 ),
                 Direction::North,
@@ -144,14 +235,14 @@ impl Board {
         for (percentage, tile, can_be_close_to_exit) in [
             (
                 ((desc.weak_walls_percentage_min..=desc.weak_walls_percentage_max)
-                    .choose(&mut rng)
+                    .choose(rng)
                     .unwrap_or(0)),
                 Tile::WeakWall,
                 true,
             ),
             (
                 ((desc.pilars_percentage_min..=desc.pilars_percentage_max)
-                    .choose(&mut rng)
+                    .choose(rng)
                     .unwrap_or(0)),
                 Tile::Wall,
                 true,
@@ -160,15 +251,29 @@ impl Board {
                 ((desc.box_percentage_min..=desc.box_percentage_max)
                     .clone()
                     .into_iter()
-                    .choose(&mut rng)
+                    .choose(rng)
                     .unwrap_or(0)),
                 Tile::Box,
                 false,
             ),
+            (
+                ((desc.stop_percentage_min..=desc.stop_percentage_max)
+                    .choose(rng)
+                    .unwrap_or(0)),
+                Tile::Stop,
+                true,
+            ),
+            (
+                ((desc.cracked_ice_percentage_min..=desc.cracked_ice_percentage_max)
+                    .choose(rng)
+                    .unwrap_or(0)),
+                Tile::CrackedIce,
+                true,
+            ),
         ] {
             for _ in 0..((width - 2) * (height - 2)) * percentage / 100 {
-                let x = (1..(width - 1) as usize).choose(&mut rng).unwrap();
-                let y = (1..(height - 1) as usize).choose(&mut rng).unwrap();
+                let x = (1..(width - 1) as usize).choose(rng).unwrap();
+                let y = (1..(height - 1) as usize).choose(rng).unwrap();
 
                 if !can_be_close_to_exit
                     && ((x as isize - end.x).abs() < 3 || (y as isize - end.y).abs() > 3)
@@ -182,20 +287,20 @@ impl Board {
 
         let vignet = (width * height)
             * ((desc.vignet_percentage_min..=desc.vignet_percentage_max)
-                .choose(&mut rng)
+                .choose(rng)
                 .unwrap_or(0))
             / 100;
 
         for _ in 0..vignet {
-            let x = (1..(width - 1) as usize).choose(&mut rng).unwrap();
-            let y = (1..(height - 1) as usize).choose(&mut rng).unwrap();
+            let x = (1..(width - 1) as usize).choose(rng).unwrap();
+            let y = (1..(height - 1) as usize).choose(rng).unwrap();
 
             let normal_x = (x as f32 / width as f32) - 0.5;
             let normal_y = (y as f32 / height as f32) - 0.5;
 
             let normal_d = normal_x * normal_x + normal_y * normal_y;
 
-            if random::<f32>() > normal_d * 2. {
+            if rng.random::<f32>() > normal_d * 2. {
                 map[y][x] = Tile::Wall;
             }
 
@@ -212,8 +317,86 @@ impl Board {
             start_direction,
             &mut end,
             end_direction,
+            rng,
         );
 
+        let entrance = start + start_direction.vector();
+        let exit = end + end_direction.vector();
+
+        // The percentage counts portals, two to a pair, and ids have to fit in
+        // a single digit of the lobby format
+        let portal_pairs = ((width - 2) * (height - 2))
+            * ((desc.portal_percentage_min..=desc.portal_percentage_max)
+                .choose(rng)
+                .unwrap_or(0))
+            / 200;
+
+        for id in 0..portal_pairs.min(10) {
+            let pair = map
+                .all_inner_pos()
+                .filter(|p| map.at(p) == Tile::Ice && *p != entrance && *p != exit)
+                .choose_multiple(rng, 2);
+
+            if pair.len() < 2 {
+                break;
+            }
+
+            for p in &pair {
+                map.set(p, Tile::Portal { id });
+            }
+        }
+
+        let arrows = ((width - 2) * (height - 2))
+            * ((desc.arrow_percentage_min..=desc.arrow_percentage_max)
+                .choose(rng)
+                .unwrap_or(0))
+            / 100;
+
+        for _ in 0..arrows {
+            let Some(p) = map
+                .all_inner_pos()
+                .filter(|p| map.at(p) == Tile::Ice && *p != entrance && *p != exit)
+                .choose(rng)
+            else {
+                break;
+            };
+
+            let direction = *Direction::all().iter().choose(rng).unwrap();
+
+            map.set(&p, Tile::Arrow(direction));
+        }
+
+        let key_lock_pairs = (desc.key_lock_pairs_min..=desc.key_lock_pairs_max)
+            .choose(rng)
+            .unwrap_or(0)
+            .clamp(0, 10);
+
+        // The first lock sits right in front of the exit so its key can't be
+        // skipped, the rest go anywhere and only boards whose optimal routes
+        // pick up every key are kept
+        for id in 0..key_lock_pairs {
+            let mut free = map
+                .all_inner_pos()
+                .filter(|p| map.at(p) == Tile::Ice && *p != entrance && *p != exit)
+                .collect::<Vec<_>>();
+
+            let lock = match id {
+                0 => exit,
+                _ => match free.iter().choose(rng) {
+                    Some(p) => *p,
+                    None => break,
+                },
+            };
+            free.retain(|p| *p != lock);
+
+            let Some(key) = free.iter().choose(rng) else {
+                break;
+            };
+
+            map.set(&lock, Tile::Lock { key: Some(id) });
+            map.set(key, Tile::Key { id });
+        }
+
         map.set(
             &end,
             Tile::Gate(GateMetadata::Exit {
@@ -0,0 +1,702 @@
+use std::fs;
+
+use crate::{
+    api::{
+        board_description::{BoardDescription, GameMode},
+        direction::Direction,
+        main::{EndOfGameMetadata, GateDestination, GateMetadata, RoomIdAndGate},
+        pos::Pos,
+        tile::Tile,
+    },
+    logic::{
+        board::Board,
+        gate::GateEntry,
+        matrix::{Matrix, TileMap},
+        solver::{Analysis, Route},
+    },
+};
+
+// A small self describing binary format: integers are LEB128 varints (signed
+// ones zigzag encoded first), collections are prefixed by their length.
+pub trait Codec: Sized {
+    fn write(&self, w: &mut Writer);
+    fn read(r: &mut Reader) -> Result<Self, String>;
+}
+
+#[derive(Default)]
+pub struct Writer {
+    pub bytes: Vec<u8>,
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Writer {
+    pub fn put<T: Codec>(&mut self, value: &T) -> &mut Self {
+        value.write(self);
+        self
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.byte(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.byte(value as u8);
+    }
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, at: 0 }
+    }
+
+    pub fn get<T: Codec>(&mut self) -> Result<T, String> {
+        T::read(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.at == self.bytes.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.at)
+            .ok_or(format!("unexpected end of data at byte {}", self.at))?;
+        self.at += 1;
+        Ok(byte)
+    }
+
+    // Lengths come straight from the data, so a corrupt one may point past
+    // the end of the address space
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .at
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.at..end))
+            .ok_or(format!("unexpected end of data at byte {}", self.at))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut ret = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            ret |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(ret);
+            }
+        }
+        Err(format!("varint too long at byte {}", self.at))
+    }
+
+    fn tag(&mut self, what: &str, max: u8) -> Result<u8, String> {
+        let tag = self.byte()?;
+        if tag > max {
+            return Err(format!("unknown {what} tag {tag} at byte {}", self.at - 1));
+        }
+        Ok(tag)
+    }
+}
+
+// Files start with a four byte magic and a version, so readers can reject
+// files that aren't theirs or were written by a newer release. They are
+// written next to the target and renamed over it, so a crash mid write leaves
+// the previous file intact.
+pub fn write_file<T: Codec>(
+    path: &str,
+    magic: &[u8; 4],
+    version: u64,
+    body: &T,
+) -> Result<(), String> {
+    let mut w = Writer::default();
+    w.bytes.extend_from_slice(magic);
+    w.varint(version);
+    w.put(body);
+
+    let tmp_path = format!("{path}.tmp");
+    fs::write(&tmp_path, w.bytes).map_err(|e| format!("couldnt write {tmp_path}: {e}"))?;
+    fs::rename(&tmp_path, path).map_err(|e| format!("couldnt replace {path}: {e}"))
+}
+
+pub fn read_file<T: Codec>(path: &str, magic: &[u8; 4], version: u64) -> Result<T, String> {
+    let bytes = fs::read(path).map_err(|e| format!("couldnt read {path}: {e}"))?;
+
+    read_bytes(&bytes, magic, version).map_err(|e| format!("{path}: {e}"))
+}
+
+// The same as read_file, for files that don't live on the file system, like
+// the app's bundled assets
+pub fn read_bytes<T: Codec>(bytes: &[u8], magic: &[u8; 4], version: u64) -> Result<T, String> {
+    let mut r = Reader::new(bytes);
+
+    if r.take(4)? != magic {
+        return Err(format!("not a {} file", String::from_utf8_lossy(magic)));
+    }
+
+    let found_version = r.varint()?;
+    if found_version != version {
+        return Err(format!("version {found_version}, expected {version}"));
+    }
+
+    let ret = r.get()?;
+
+    if !r.is_empty() {
+        return Err("trailing data".to_string());
+    }
+
+    Ok(ret)
+}
+
+impl Codec for u64 {
+    fn write(&self, w: &mut Writer) {
+        w.varint(*self);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        r.varint()
+    }
+}
+
+impl Codec for usize {
+    fn write(&self, w: &mut Writer) {
+        w.varint(*self as u64);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(r.varint()? as usize)
+    }
+}
+
+impl Codec for isize {
+    fn write(&self, w: &mut Writer) {
+        let value = *self as i64;
+        w.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let value = r.varint()?;
+        Ok(((value >> 1) as i64 ^ -((value & 1) as i64)) as isize)
+    }
+}
+
+impl Codec for f32 {
+    fn write(&self, w: &mut Writer) {
+        w.bytes.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(f32::from_le_bytes(r.take(4)?.try_into().unwrap()))
+    }
+}
+
+impl Codec for String {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.len());
+        w.bytes.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let len = r.get::<usize>()?;
+        String::from_utf8(r.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.len());
+        for e in self {
+            w.put(e);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        (0..r.get::<usize>()?).map(|_| r.get()).collect()
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn write(&self, w: &mut Writer) {
+        match self {
+            None => w.byte(0),
+            Some(e) => {
+                w.byte(1);
+                w.put(e);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        match r.tag("option", 1)? {
+            0 => Ok(None),
+            _ => Ok(Some(r.get()?)),
+        }
+    }
+}
+
+impl<T: Codec> Codec for Box<T> {
+    fn write(&self, w: &mut Writer) {
+        w.put(self.as_ref());
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(Box::new(r.get()?))
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.0).put(&self.1);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok((r.get()?, r.get()?))
+    }
+}
+
+impl Codec for Pos {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.x).put(&self.y);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(Pos::new(r.get()?, r.get()?))
+    }
+}
+
+impl Codec for Direction {
+    fn write(&self, w: &mut Writer) {
+        w.byte(match self {
+            Direction::North => 0,
+            Direction::South => 1,
+            Direction::East => 2,
+            Direction::West => 3,
+        });
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(match r.tag("direction", 3)? {
+            0 => Direction::North,
+            1 => Direction::South,
+            2 => Direction::East,
+            _ => Direction::West,
+        })
+    }
+}
+
+impl Codec for GameMode {
+    fn write(&self, w: &mut Writer) {
+        w.put(&isize::from(self));
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(r.get::<isize>()?.into())
+    }
+}
+
+impl Codec for BoardDescription {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.as_list());
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let list = r.get::<Vec<isize>>()?;
+
+        if list.len() != BoardDescription::LIST_LEN {
+            return Err(format!(
+                "board description has {} fields, expected {}",
+                list.len(),
+                BoardDescription::LIST_LEN
+            ));
+        }
+
+        Ok(BoardDescription::from_list(list))
+    }
+}
+
+impl Codec for RoomIdAndGate {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.room_id).put(&self.gate_id);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(RoomIdAndGate {
+            room_id: r.get()?,
+            gate_id: r.get()?,
+        })
+    }
+}
+
+impl Codec for EndOfGameMetadata {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.level)
+            .put(&self.gamemode_desc)
+            .put(&self.return_gate)
+            .put(&self.best_score_id);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(EndOfGameMetadata {
+            level: r.get()?,
+            gamemode_desc: r.get()?,
+            return_gate: r.get()?,
+            best_score_id: r.get()?,
+        })
+    }
+}
+
+impl Codec for GateDestination {
+    fn write(&self, w: &mut Writer) {
+        match self {
+            GateDestination::NextAutoGen => w.byte(0),
+            GateDestination::FirstAutogen {
+                board_description,
+                board_count,
+                end_of_game_metadata,
+            } => {
+                w.byte(1);
+                w.put(board_description)
+                    .put(board_count)
+                    .put(end_of_game_metadata);
+            }
+            GateDestination::RoomIdWithGate(room_id_and_gate) => {
+                w.byte(2);
+                w.put(room_id_and_gate);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(match r.tag("gate destination", 2)? {
+            0 => GateDestination::NextAutoGen,
+            1 => GateDestination::FirstAutogen {
+                board_description: r.get()?,
+                board_count: r.get()?,
+                end_of_game_metadata: r.get()?,
+            },
+            _ => GateDestination::RoomIdWithGate(r.get()?),
+        })
+    }
+}
+
+impl Codec for GateMetadata {
+    fn write(&self, w: &mut Writer) {
+        match self {
+            GateMetadata::EntryOnly => w.byte(0),
+            GateMetadata::Exit { destination, label } => {
+                w.byte(1);
+                w.put(destination).put(label);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(match r.tag("gate metadata", 1)? {
+            0 => GateMetadata::EntryOnly,
+            _ => GateMetadata::Exit {
+                destination: r.get()?,
+                label: r.get()?,
+            },
+        })
+    }
+}
+
+impl Codec for Tile {
+    fn write(&self, w: &mut Writer) {
+        match self {
+            Tile::Outside => w.byte(0),
+            Tile::Wall => w.byte(1),
+            Tile::Ice => w.byte(2),
+            Tile::Stop => w.byte(3),
+            Tile::WeakWall => w.byte(4),
+            Tile::Box => w.byte(5),
+            Tile::Lock { key } => {
+                w.byte(6);
+                w.put(key);
+            }
+            Tile::Gate(metadata) => {
+                w.byte(7);
+                w.put(metadata);
+            }
+            Tile::Sign {
+                text,
+                width,
+                height,
+            } => {
+                w.byte(8);
+                w.put(text).put(width).put(height);
+            }
+            Tile::Key { id } => {
+                w.byte(9);
+                w.put(id);
+            }
+            Tile::Portal { id } => {
+                w.byte(10);
+                w.put(id);
+            }
+            Tile::Arrow(direction) => {
+                w.byte(11);
+                w.put(direction);
+            }
+            Tile::CrackedIce => w.byte(12),
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(match r.tag("tile", 12)? {
+            0 => Tile::Outside,
+            1 => Tile::Wall,
+            2 => Tile::Ice,
+            3 => Tile::Stop,
+            4 => Tile::WeakWall,
+            5 => Tile::Box,
+            6 => Tile::Lock { key: r.get()? },
+            7 => Tile::Gate(r.get()?),
+            8 => Tile::Sign {
+                text: r.get()?,
+                width: r.get()?,
+                height: r.get()?,
+            },
+            9 => Tile::Key { id: r.get()? },
+            10 => Tile::Portal { id: r.get()? },
+            11 => Tile::Arrow(r.get()?),
+            _ => Tile::CrackedIce,
+        })
+    }
+}
+
+impl Codec for TileMap {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.get_width()).put(&self.get_height());
+        for row in &self.0 {
+            for tile in row {
+                w.put(tile);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let width = r.get::<isize>()?;
+        let height = r.get::<isize>()?;
+
+        if width <= 0 || height <= 0 {
+            return Err(format!("invalid map size {width}x{height}"));
+        }
+
+        (0..height)
+            .map(|_| (0..width).map(|_| r.get()).collect())
+            .collect::<Result<_, _>>()
+            .map(Matrix)
+    }
+}
+
+impl Codec for GateEntry {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.pos).put(&self.inwards_direction);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(GateEntry {
+            pos: r.get()?,
+            inwards_direction: r.get()?,
+        })
+    }
+}
+
+impl Codec for Board {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.map).put(&self.gates);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let ret = Board {
+            map: r.get()?,
+            gates: r.get()?,
+        };
+
+        if let Some(gate) = ret.gates.iter().find(|g| !ret.map.in_bounds(&g.pos)) {
+            return Err(format!("gate at {:?} is outside the map", gate.pos));
+        }
+
+        Ok(ret)
+    }
+}
+
+impl Codec for Route {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.solution);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let ret = Route { solution: r.get()? };
+
+        if ret.solution.is_empty() {
+            return Err("route without moves".to_string());
+        }
+
+        Ok(ret)
+    }
+}
+
+impl Codec for Analysis {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.optimal_movement_count).put(&self.routes);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let ret = Analysis {
+            optimal_movement_count: r.get()?,
+            routes: r.get()?,
+        };
+
+        if ret.routes.first().is_none_or(|routes| routes.is_empty()) {
+            return Err("analysis without an optimal route".to_string());
+        }
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::api::lobby_text::parse_lobby;
+
+    const MAGIC: &[u8; 4] = b"TEST";
+
+    fn encode<T: Codec>(value: &T) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.put(value);
+        w.bytes
+    }
+
+    fn with_header(version: u64, body: &[u8]) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes.extend_from_slice(MAGIC);
+        w.varint(version);
+        w.bytes.extend_from_slice(body);
+        w.bytes
+    }
+
+    fn sample_board() -> Board {
+        let first_autogen = GateMetadata::Exit {
+            destination: GateDestination::FirstAutogen {
+                board_description: Box::new(BoardDescription {
+                    game_mode: GameMode::FindPerfectPath,
+                    portal_percentage_max: 5,
+                    fitness_function: -3,
+                    ..BoardDescription::default()
+                }),
+                board_count: 4,
+                end_of_game_metadata: EndOfGameMetadata {
+                    level: 2,
+                    gamemode_desc: "perfect path".to_string(),
+                    return_gate: RoomIdAndGate {
+                        room_id: "lobby".to_string(),
+                        gate_id: 1,
+                    },
+                    best_score_id: "best".to_string(),
+                },
+            },
+            label: Some("2".to_string()),
+        };
+
+        parse_lobby(
+            "# # A # # # # \n\
+             #   w b s c # \n\
+             # ^ > v < S # \n\
+             # k3l3l p1p1# \n\
+             # # E # # # # \n",
+            &HashMap::from([(b'A', first_autogen)]),
+            vec![("hello".to_string(), 2, 1)],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn board_round_trips() {
+        let board = sample_board();
+        let bytes = encode(&board);
+        let back: Board = read_bytes(&with_header(1, &bytes), MAGIC, 1).unwrap();
+
+        assert_eq!(back.map, board.map);
+        assert_eq!(encode(&back), bytes);
+    }
+
+    #[test]
+    fn description_round_trips() {
+        let desc = BoardDescription {
+            game_mode: GameMode::FindPerfectPath,
+            min_optimal_moves: -1,
+            stop_percentage_max: 7,
+            fitness_function: isize::MIN,
+            ..BoardDescription::default()
+        };
+        let back: BoardDescription = Reader::new(&encode(&desc)).get().unwrap();
+
+        assert_eq!(back, desc);
+    }
+
+    #[test]
+    fn truncated_data() {
+        let mut bytes = encode(&sample_board());
+        bytes.pop();
+
+        let e = read_bytes::<Board>(&with_header(1, &bytes), MAGIC, 1).unwrap_err();
+        assert!(e.starts_with("unexpected end of data"), "{e}");
+    }
+
+    #[test]
+    fn corrupt_length() {
+        let e = read_bytes::<String>(&with_header(1, &encode(&u64::MAX)), MAGIC, 1).unwrap_err();
+
+        assert!(e.starts_with("unexpected end of data"), "{e}");
+    }
+
+    #[test]
+    fn wrong_header_or_trailing_data() {
+        let body = encode(&"body".to_string());
+
+        let e = read_bytes::<String>(&with_header(1, &body), b"ICEP", 1).unwrap_err();
+        assert_eq!(e, "not a ICEP file");
+
+        let e = read_bytes::<String>(&with_header(2, &body), MAGIC, 1).unwrap_err();
+        assert_eq!(e, "version 2, expected 1");
+
+        let mut bytes = with_header(1, &body);
+        bytes.push(0);
+        let e = read_bytes::<String>(&bytes, MAGIC, 1).unwrap_err();
+        assert_eq!(e, "trailing data");
+    }
+
+    #[test]
+    fn empty_routes() {
+        let empty_route = Analysis {
+            optimal_movement_count: 0,
+            routes: vec![vec![Route { solution: vec![] }]],
+        };
+        let e =
+            read_bytes::<Analysis>(&with_header(1, &encode(&empty_route)), MAGIC, 1).unwrap_err();
+        assert_eq!(e, "route without moves");
+
+        // Built in memory it doesn't solve anything, instead of panicking
+        assert!(!empty_route.check_still_applies(&sample_board(), 0));
+
+        let no_route = Analysis {
+            optimal_movement_count: 0,
+            routes: vec![vec![]],
+        };
+        let e = read_bytes::<Analysis>(&with_header(1, &encode(&no_route)), MAGIC, 1).unwrap_err();
+        assert_eq!(e, "analysis without an optimal route");
+    }
+}
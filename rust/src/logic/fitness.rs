@@ -0,0 +1,194 @@
+use std::sync::Mutex;
+
+use crate::{
+    api::board_description::{BoardDescription, FitnessWeights, GameMode},
+    logic::{matrix::TileMap, solver::Analysis},
+};
+
+pub trait FitnessFunction: Send {
+    fn score(&self, analysis: &Analysis, tile_map: &TileMap) -> f32;
+}
+
+pub type FitnessFactory = fn(&BoardDescription) -> Box<dyn FitnessFunction>;
+
+static G_FITNESS_FACTORIES: Mutex<Vec<(isize, FitnessFactory)>> = Mutex::new(Vec::new());
+
+// Ids are open so a new game mode can bring its own scorer without touching
+// GameMode, descriptions pick it through their fitness_function id. 0 stands
+// for the built-in scorers and can't be taken.
+pub fn register_fitness_function(id: isize, factory: FitnessFactory) -> Result<(), String> {
+    if id == 0 {
+        return Err("fitness function id 0 is reserved for the built-in ones".to_string());
+    }
+
+    let mut factories = G_FITNESS_FACTORIES.lock().unwrap();
+
+    factories.retain(|(registered, _)| *registered != id);
+    factories.push((id, factory));
+
+    Ok(())
+}
+
+pub fn fitness_function(
+    board_description: &BoardDescription,
+) -> Result<Box<dyn FitnessFunction>, String> {
+    let id = board_description.fitness_function;
+
+    if id != 0 {
+        return G_FITNESS_FACTORIES
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(registered, _)| *registered == id)
+            .map(|(_, factory)| factory(board_description))
+            .ok_or(format!("no fitness function registered as {id}"));
+    }
+
+    let weights = board_description.fitness_weights.clone();
+
+    Ok(match board_description.game_mode {
+        GameMode::FindExit => Box::new(FindExitFitness { weights }),
+        GameMode::FindPerfectPath => Box::new(PerfectPathFitness { weights }),
+    })
+}
+
+// Rewards rooms whose optimal routes are full of tempting turns, and which
+// have plenty of slightly longer routes to fall into.
+pub struct PerfectPathFitness {
+    pub weights: FitnessWeights,
+}
+
+impl FitnessFunction for PerfectPathFitness {
+    fn score(&self, analysis: &Analysis, tile_map: &TileMap) -> f32 {
+        let good_route_fitness = analysis.routes[0]
+            .iter()
+            .map(|route| route.prefect_path_fitness(tile_map, &self.weights))
+            .fold(f32::INFINITY, f32::min);
+
+        let near_optimal_routes = analysis.routes[1..]
+            .iter()
+            .map(|routes| routes.len())
+            .sum::<usize>();
+
+        let solution_distribution =
+            near_optimal_routes as f32 / (1. + analysis.routes[0].len() as f32);
+
+        good_route_fitness * solution_distribution
+    }
+}
+
+// Rewards rooms with few ways out, all of them hard to spot.
+pub struct FindExitFitness {
+    pub weights: FitnessWeights,
+}
+
+impl FitnessFunction for FindExitFitness {
+    fn score(&self, analysis: &Analysis, tile_map: &TileMap) -> f32 {
+        let routes = analysis.routes.iter().flatten();
+
+        let min_fitness = routes
+            .clone()
+            .map(|route| route.any_path_fitness(tile_map, &self.weights))
+            .fold(f32::INFINITY, f32::min);
+
+        min_fitness / routes.count() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{direction::Direction, generator::GeneratorConfig, pos::Pos, tile::Tile},
+        logic::{
+            matrix::Matrix,
+            solver::{Route, SolverLimits},
+            worker_pool::WorkerPool,
+        },
+    };
+
+    struct Constant;
+
+    impl FitnessFunction for Constant {
+        fn score(&self, _: &Analysis, _: &TileMap) -> f32 {
+            42.
+        }
+    }
+
+    fn score(desc: &BoardDescription) -> Result<f32, String> {
+        let analysis = Analysis {
+            optimal_movement_count: 1,
+            routes: vec![vec![Route {
+                solution: vec![(Direction::East, Pos::new(1, 0))],
+            }]],
+        };
+
+        analysis.compute_fitness(&Matrix(vec![vec![Tile::Ice, Tile::Ice]]), desc)
+    }
+
+    // The registry is shared by every test, so each one keeps to its own ids
+    #[test]
+    fn registered_functions_are_picked_by_id() {
+        register_fitness_function(17, |_| Box::new(Constant)).unwrap();
+
+        let desc = BoardDescription {
+            fitness_function: 17,
+            ..BoardDescription::default()
+        };
+
+        assert_eq!(score(&desc), Ok(42.));
+        assert_ne!(score(&BoardDescription::default()), Ok(42.));
+    }
+
+    #[test]
+    fn id_0_is_reserved() {
+        assert!(register_fitness_function(0, |_| Box::new(Constant)).is_err());
+    }
+
+    #[test]
+    fn unknown_ids_are_errors() {
+        let desc = BoardDescription {
+            fitness_function: 404,
+            ..BoardDescription::default()
+        };
+
+        assert_eq!(
+            score(&desc),
+            Err("no fitness function registered as 404".to_string())
+        );
+
+        let pool = WorkerPool::start(
+            desc,
+            1,
+            Some(0),
+            GeneratorConfig {
+                thread_count: Some(1),
+                ..GeneratorConfig::default()
+            },
+        );
+        assert!(pool.is_err());
+    }
+
+    #[test]
+    fn perfect_path_searches_at_least_one_near_optimal_tier() {
+        let desc = BoardDescription {
+            game_mode: GameMode::FindPerfectPath,
+            near_optimal_tiers: 0,
+            ..BoardDescription::default()
+        };
+
+        assert_eq!(SolverLimits::from(&desc).near_optimal_tiers, 1);
+
+        let find_exit = BoardDescription {
+            game_mode: GameMode::FindExit,
+            ..desc.clone()
+        };
+        assert_eq!(SolverLimits::from(&find_exit).near_optimal_tiers, 0);
+
+        let registered = BoardDescription {
+            fitness_function: 17,
+            ..desc
+        };
+        assert_eq!(SolverLimits::from(&registered).near_optimal_tiers, 0);
+    }
+}
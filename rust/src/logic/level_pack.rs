@@ -0,0 +1,176 @@
+use crate::{
+    api::board_description::BoardDescription,
+    logic::{
+        board::Board,
+        codec::{read_bytes, read_file, write_file, Codec, Reader, Writer},
+        solver::Analysis,
+    },
+};
+
+const PACK_MAGIC: &[u8; 4] = b"ICEP";
+const PACK_VERSION: u64 = 6;
+
+#[derive(Clone, Debug)]
+pub struct PackedBoard {
+    pub board: Board,
+    pub analysis: Analysis,
+    pub fitness: f32,
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct PackedLevel {
+    pub board_desc: BoardDescription,
+    pub boards: Vec<PackedBoard>,
+}
+
+// Boards generated offline, grouped by the description they were generated
+// for so a campaign can ship every level in a single file.
+#[derive(Clone, Debug, Default)]
+pub struct LevelPack {
+    pub levels: Vec<PackedLevel>,
+}
+
+impl LevelPack {
+    pub fn read(path: &str) -> Result<Self, String> {
+        read_file(path, PACK_MAGIC, PACK_VERSION)
+    }
+
+    // Packs shipped with the app are assets rather than files on Android and
+    // iOS, so Dart loads them and hands over the bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        read_bytes(bytes, PACK_MAGIC, PACK_VERSION)
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        write_file(path, PACK_MAGIC, PACK_VERSION, self)
+    }
+
+    pub fn push(&mut self, board_desc: &BoardDescription, board: PackedBoard) {
+        match self.levels.iter_mut().find(|e| e.board_desc == *board_desc) {
+            Some(level) => level.boards.push(board),
+            None => self.levels.push(PackedLevel {
+                board_desc: board_desc.clone(),
+                boards: vec![board],
+            }),
+        }
+    }
+
+    // Boards that don't replay anymore, because the rules changed since the
+    // pack was generated, are left out
+    pub fn boards_for(&self, board_desc: &BoardDescription) -> Vec<PackedBoard> {
+        self.levels
+            .iter()
+            .filter(|e| e.board_desc == *board_desc)
+            .flat_map(|e| &e.boards)
+            .filter(|e| e.analysis.check_still_applies(&e.board, 0))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Codec for PackedBoard {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.fitness)
+            .put(&self.seed)
+            .put(&self.board)
+            .put(&self.analysis);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(PackedBoard {
+            fitness: r.get()?,
+            seed: r.get()?,
+            board: r.get()?,
+            analysis: r.get()?,
+        })
+    }
+}
+
+impl Codec for PackedLevel {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.board_desc).put(&self.boards);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(PackedLevel {
+            board_desc: r.get()?,
+            boards: r.get()?,
+        })
+    }
+}
+
+impl Codec for LevelPack {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.levels);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(LevelPack { levels: r.get()? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::logic::solver::{analyze, SolverLimits};
+
+    #[test]
+    fn pack_round_trips() {
+        let desc = BoardDescription::default();
+        let other = BoardDescription {
+            area: 64,
+            ..desc.clone()
+        };
+        let mut pack = LevelPack::default();
+
+        for seed in 0..20 {
+            let Ok(board) = Board::new_random(&desc, &mut StdRng::seed_from_u64(seed)) else {
+                continue;
+            };
+            let Ok(analysis) = analyze(&board, 0, 1, &SolverLimits::default()) else {
+                continue;
+            };
+
+            pack.push(
+                if seed % 2 == 0 { &desc } else { &other },
+                PackedBoard {
+                    board,
+                    analysis,
+                    fitness: seed as f32 / 2.0,
+                    seed,
+                },
+            );
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("pack_round_trips_{}.pack", std::process::id()));
+        let path = path.to_str().unwrap();
+        pack.write(path).unwrap();
+        let bytes = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let back = LevelPack::from_bytes(&bytes).unwrap();
+
+        assert_eq!(back.levels.len(), pack.levels.len());
+        for (back, level) in back.levels.iter().zip(&pack.levels) {
+            assert_eq!(back.board_desc, level.board_desc);
+            assert_eq!(back.boards.len(), level.boards.len());
+
+            for (back, board) in back.boards.iter().zip(&level.boards) {
+                assert_eq!(back.board.map, board.board.map);
+                assert_eq!(back.seed, board.seed);
+                assert_eq!(back.fitness, board.fitness);
+                assert_eq!(
+                    back.analysis.optimal_movement_count,
+                    board.analysis.optimal_movement_count
+                );
+            }
+        }
+        assert!(!back.boards_for(&desc).is_empty());
+    }
+}
@@ -134,6 +134,16 @@ impl<T: Clone> Matrix<T> {
 }
 
 impl TileMap {
+    // A portal without a twin does nothing
+    pub fn portal_twin(&self, portal: &Pos) -> Option<Pos> {
+        let Tile::Portal { id } = self.at(portal) else {
+            return None;
+        };
+
+        self.all_pos()
+            .find(|p| p != portal && self.at(p) == Tile::Portal { id })
+    }
+
     pub fn print(&self, highlight: Vec<Pos>) {
         for (y, row) in self.0.iter().enumerate() {
             for (x, tile) in row.iter().enumerate() {
@@ -1,5 +1,6 @@
 pub mod noise_reduction;
 pub mod solver;
+pub mod fitness;
 pub mod matrix;
 pub mod worker_pool;
 mod visitations;
@@ -7,3 +8,6 @@ pub mod gate;
 pub mod neighbour;
 pub mod tile_map_wrap;
 pub mod board;
+pub mod reachability;
+pub mod codec;
+pub mod level_pack;
@@ -1,6 +1,6 @@
-use rand::seq::IteratorRandom;
+use rand::{seq::IteratorRandom, Rng};
 
-use crate::api::{main::GateMetadata, pos::Pos, tile::Tile};
+use crate::api::{direction::Direction, main::GateMetadata, pos::Pos, tile::Tile};
 
 #[derive(Clone)]
 pub struct Neighbour<T> {
@@ -36,17 +36,27 @@ impl Neighbour<Tile> {
         }
     }
 
-    pub fn get_asset(&self, p: Pos) -> Option<(String, isize)> {
-        let mut rng = rand::rng();
-        rng.reseed().expect("random initialization failed");
-
+    pub fn get_asset(&self, p: Pos, rng: &mut impl Rng) -> Option<(String, isize)> {
         match self.center {
             Tile::Gate(GateMetadata::Exit { .. })
             | Tile::Ice
             | Tile::WeakWall
             | Tile::Sign { .. }
             | Tile::Box => Some(("ice.png".into(), 0)),
-            Tile::Lock => Some(("lock.png".into(), 0)),
+            Tile::Lock { .. } => Some(("lock.png".into(), 0)),
+            Tile::Key { .. } => Some(("key.png".into(), 0)),
+            Tile::Portal { .. } => Some(("portal.png".into(), 0)),
+            Tile::CrackedIce => Some(("cracked_ice.png".into(), 0)),
+            // The asset points north and turns clockwise
+            Tile::Arrow(direction) => Some((
+                "arrow.png".into(),
+                match direction {
+                    Direction::North => 0,
+                    Direction::East => 1,
+                    Direction::South => 2,
+                    Direction::West => 3,
+                },
+            )),
             Tile::Outside => None,
             Tile::Stop => Some(("stop.png".into(), 0)),
             Tile::Wall | Tile::Gate(_) => {
@@ -94,7 +104,7 @@ impl Neighbour<Tile> {
                                 (format!(
                                     "wall/wall_simple/{}.png",
                                     (if p.parity() { 1..=4 } else { 5..=8 })
-                                        .choose(&mut rng)
+                                        .choose(rng)
                                         .unwrap()
                                 ))
                                 .into(),
@@ -1,10 +1,16 @@
-use std::{collections::VecDeque, vec};
+use std::collections::VecDeque;
 
-use rand::random;
+use rand::Rng;
 
 use crate::{
     api::{ direction::Direction, main::LeftRotatable, pos::Pos, tile::Tile},
-    logic::{board::Board, matrix::TileMap, solver::Analysis, tile_map_wrap::TileMapWrap, visitations::Visitations},
+    logic::{
+        board::Board,
+        matrix::TileMap,
+        solver::{analyze, Analysis, SolverLimits},
+        tile_map_wrap::TileMapWrap,
+        visitations::Visitations,
+    },
 };
 
 pub fn asthetic_filter(
@@ -13,6 +19,7 @@ pub fn asthetic_filter(
     start_direction: Direction,
     end: &mut Pos,
     end_direction: Direction,
+    rng: &mut impl Rng,
 ) {
     if start.x == end.x || start.y == end.y {
         let mean = (*start + *end) / 2;
@@ -40,7 +47,7 @@ pub fn asthetic_filter(
         Tile::Ice,
     );
 
-    remove_rooms(map, &start, &start_direction);
+    remove_rooms(map, &start, &start_direction, rng);
 
     map.set(&(*end + end_direction.vector()), Tile::Ice);
     map.set(
@@ -152,7 +159,12 @@ fn heuristic_distance(pos1: Pos, pos2: Pos) -> usize {
         .unwrap()
 }
 
-pub fn remove_rooms(board: &mut TileMap, start: &Pos, start_direction: &Direction) {
+pub fn remove_rooms(
+    board: &mut TileMap,
+    start: &Pos,
+    start_direction: &Direction,
+    rng: &mut impl Rng,
+) {
     let all_pos = board.all_inner_pos().collect::<Vec<_>>();
     let entrance_corridor = *start + start_direction.vector();
 
@@ -206,7 +218,7 @@ pub fn remove_rooms(board: &mut TileMap, start: &Pos, start_direction: &Directio
                         tile: Tile::Wall,
                     },
                 ) {
-                    if random::<f32>() > 0.5 {
+                    if rng.random::<f32>() > 0.5 {
                         board.set(&p1, Tile::Wall);
                     } else {
                         board.set(&p2, Tile::Wall);
@@ -222,7 +234,7 @@ pub fn remove_rooms(board: &mut TileMap, start: &Pos, start_direction: &Directio
 pub fn flood(
     starting_positions: Vec<Pos>,
     board: &TileMap,
-    traversable_tiles: Vec<Tile>,
+    traversable: impl Fn(&Tile) -> bool,
 ) -> Visitations {
     let mut reachability = Visitations::new(board.get_width(), board.get_height());
     let mut flood_edge: VecDeque<Pos> = VecDeque::from(starting_positions);
@@ -232,10 +244,13 @@ pub fn flood(
             continue;
         }
 
-        if !reachability.contains(&next_check) && traversable_tiles.contains(&board.at(&next_check))
-        {
+        if !reachability.contains(&next_check) && traversable(&board.at(&next_check)) {
             reachability.insert(&next_check);
 
+            if let Some(twin) = board.portal_twin(&next_check) {
+                flood_edge.push_back(twin);
+            }
+
             for dir in [
                 Direction::North,
                 Direction::South,
@@ -249,6 +264,54 @@ pub fn flood(
     reachability
 }
 
+// A stop no optimal route ends a move on is just in the way, so it goes back
+// to ice. That can open up shorter routes, so the board is solved again until
+// every stop left is used.
+pub fn strip_unused_stops(
+    mut board: Board,
+    mut analysis: Analysis,
+    limits: &SolverLimits,
+) -> Result<(Board, Analysis), String> {
+    loop {
+        let used = analysis.routes[0]
+            .iter()
+            .flat_map(|route| route.used_stops(&board.map))
+            .collect::<Vec<_>>();
+
+        let unused = board
+            .map
+            .all_pos()
+            .filter(|p| board.map.at(p) == Tile::Stop && !used.contains(p))
+            .collect::<Vec<_>>();
+
+        if unused.is_empty() {
+            return Ok((board, analysis));
+        }
+
+        for p in &unused {
+            board.map.set(p, Tile::Ice);
+        }
+
+        analysis = analyze(&board, 0, 1, limits)?;
+    }
+}
+
+// Keyed locks open at some point, so whatever is behind them stays
+fn is_part_of_the_room(tile: &Tile) -> bool {
+    matches!(
+        tile,
+        Tile::Ice
+            | Tile::Stop
+            | Tile::WeakWall
+            | Tile::Box
+            | Tile::Key { .. }
+            | Tile::Lock { key: Some(_) }
+            | Tile::Portal { .. }
+            | Tile::Arrow(_)
+            | Tile::CrackedIce
+    )
+}
+
 pub fn asthetic_cleanup(mut ret: Board, _: &Analysis, _: usize) -> Board {
     // assert!(analysis.check_still_applies(&ret, initial_gate_id));
     let inner_pos = ret.map.all_inner_pos().collect::<Vec<_>>();
@@ -259,7 +322,7 @@ pub fn asthetic_cleanup(mut ret: Board, _: &Analysis, _: usize) -> Board {
             .map(|g| g.pos + g.inwards_direction.vector())
             .collect(),
         &ret.map,
-        vec![Tile::Ice, Tile::WeakWall, Tile::Box],
+        is_part_of_the_room,
     );
 
     for p in &inner_pos {
@@ -289,7 +352,7 @@ pub fn asthetic_cleanup(mut ret: Board, _: &Analysis, _: usize) -> Board {
             .map(|g| g.pos + g.inwards_direction.vector())
             .collect(),
         &ret.map,
-        vec![Tile::Ice, Tile::WeakWall, Tile::Box],
+        is_part_of_the_room,
     );
 
     for p in &inner_pos {
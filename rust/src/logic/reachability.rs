@@ -0,0 +1,209 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    api::{direction::Direction, pos::Pos},
+    logic::{board::Board, matrix::TileMap, solver::step},
+};
+
+// Unlike the solver this search has no depth limit, every distinct
+// (position, board) pair reachable from the start is visited once.
+pub struct StateGraph {
+    edges: Vec<Vec<usize>>,
+    goals: Vec<bool>,
+}
+
+impl StateGraph {
+    pub fn explore(board: &Board, start: Pos, targets: &[Pos]) -> Self {
+        let mut ids: HashMap<(Pos, TileMap), usize> = HashMap::new();
+        let mut edges = vec![vec![]];
+        let mut goals = vec![targets.contains(&start)];
+        let mut pending = VecDeque::from([(start, board.clone(), 0)]);
+
+        ids.insert((start, board.map.clone()), 0);
+
+        while let Some((pos, board, id)) = pending.pop_front() {
+            if goals[id] {
+                continue;
+            }
+
+            for direction in Direction::all() {
+                let (new_pos, new_board) = play(&board, pos, &direction);
+
+                if new_pos == pos && new_board.map == board.map {
+                    continue;
+                }
+
+                let key = (new_pos, new_board.map.clone());
+                let new_id = match ids.get(&key) {
+                    Some(new_id) => *new_id,
+                    None => {
+                        let new_id = edges.len();
+                        ids.insert(key, new_id);
+                        edges.push(vec![]);
+                        goals.push(targets.contains(&new_pos));
+                        pending.push_back((new_pos, new_board, new_id));
+                        new_id
+                    }
+                };
+
+                edges[id].push(new_id);
+            }
+        }
+
+        Self { edges, goals }
+    }
+
+    // A state is alive if some sequence of moves still reaches a goal from it
+    fn alive(&self) -> Vec<bool> {
+        let mut reverse = vec![vec![]; self.edges.len()];
+        for (from, tos) in self.edges.iter().enumerate() {
+            for to in tos {
+                reverse[*to].push(from);
+            }
+        }
+
+        let mut alive = self.goals.clone();
+        let mut pending = (0..self.goals.len())
+            .filter(|id| self.goals[*id])
+            .collect::<VecDeque<_>>();
+
+        while let Some(id) = pending.pop_front() {
+            for from in &reverse[id] {
+                if !alive[*from] {
+                    alive[*from] = true;
+                    pending.push_back(*from);
+                }
+            }
+        }
+
+        alive
+    }
+
+    pub fn start_is_alive(&self) -> bool {
+        self.alive()[0]
+    }
+
+    pub fn trap_move_count(&self) -> usize {
+        let alive = self.alive();
+
+        self.edges
+            .iter()
+            .enumerate()
+            .filter(|(from, _)| alive[*from])
+            .flat_map(|(_, tos)| tos)
+            .filter(|to| !alive[**to])
+            .count()
+    }
+}
+
+fn play(board: &Board, start: Pos, direction: &Direction) -> (Pos, Board) {
+    let step = step(&board.map, &start, direction);
+    let mut board = board.clone();
+
+    if step.changes_board() {
+        board.resolve_step(&step, direction);
+    }
+
+    (step.pos, board)
+}
+
+pub fn exit_reachable(board: &Board, start: Pos, exit_gate_id: usize) -> bool {
+    StateGraph::explore(board, start, &[board.get_gate_position(exit_gate_id)]).start_is_alive()
+}
+
+pub fn count_trap_moves(board: &Board, entry_gate_id: usize, exit_gate_id: usize) -> usize {
+    StateGraph::explore(
+        board,
+        board.get_gate_position(entry_gate_id),
+        &[board.get_gate_position(exit_gate_id)],
+    )
+    .trap_move_count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::api::{
+        lobby_text::parse_lobby,
+        main::{GateDestination, GateMetadata},
+    };
+
+    // Gate 0 is the exit on the right, gate 1 the entrance at the bottom
+    fn board(serialized: &str) -> Board {
+        parse_lobby(
+            serialized,
+            &HashMap::from([(
+                b'A',
+                GateMetadata::Exit {
+                    destination: GateDestination::NextAutoGen,
+                    label: None,
+                },
+            )]),
+            vec![],
+        )
+        .unwrap()
+    }
+
+    fn box_room() -> Board {
+        board(
+            "# # # # # # # \n\
+             #   w       # \n\
+             #           # \n\
+             #     b     A \n\
+             # E # # # # # \n",
+        )
+    }
+
+    #[test]
+    fn pushing_the_box_against_the_exit_is_a_trap() {
+        let board = box_room();
+        let start = board.get_gate_position(1);
+
+        assert!(exit_reachable(&board, start, 0));
+        assert_eq!(count_trap_moves(&board, 1, 0), 2);
+
+        let (pos, board) = [Direction::North, Direction::South, Direction::East]
+            .iter()
+            .fold((start, board), |(pos, board), direction| {
+                play(&board, pos, direction)
+            });
+
+        assert_eq!(pos, Pos::new(2, 3));
+        assert!(!exit_reachable(&board, pos, 0));
+    }
+
+    #[test]
+    fn rooms_without_traps() {
+        let board = board(
+            "# # # # # # # \n\
+             #           # \n\
+             #           A \n\
+             #     b     # \n\
+             # E # # # # # \n",
+        );
+
+        assert!(exit_reachable(&board, board.get_gate_position(1), 0));
+        assert_eq!(count_trap_moves(&board, 1, 0), 0);
+    }
+
+    #[test]
+    fn unsolvable_rooms_are_dead_from_the_start() {
+        let board = board(
+            "# # # # # # # \n\
+             #           # \n\
+             #     b     A \n\
+             #           # \n\
+             # E # # # # # \n",
+        );
+        let graph = StateGraph::explore(
+            &board,
+            board.get_gate_position(1),
+            &[board.get_gate_position(0)],
+        );
+
+        assert!(!graph.start_is_alive());
+        assert_eq!(graph.trap_move_count(), 0);
+    }
+}
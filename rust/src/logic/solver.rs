@@ -1,19 +1,54 @@
-use std::{collections::VecDeque, rc::Rc};
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
 
 use itertools::Itertools;
 
 use crate::{
     api::{
-        board_description::{ BoardDescription, GameMode},
+        board_description::{BoardDescription, FitnessWeights, GameMode},
         direction::Direction,
         pos::Pos,
         tile::{ Tile},
     },
-    logic::{board::Board, matrix::TileMap, visitations::Visitations},
+    logic::{
+        board::{Board, BoardChange},
+        fitness::fitness_function,
+        matrix::TileMap,
+        visitations::Visitations,
+    },
 };
 
-const EXTRA_MOVES_SEARCH_MARGIN: usize = 3;
-const TOP_SOLUTION_SIZE: usize = 10;
+#[derive(Clone, Debug)]
+pub struct SolverLimits {
+    pub max_solution_depth: usize,
+    pub near_optimal_tiers: usize,
+}
+
+// The limits a room generated from the default description is checked with
+impl Default for SolverLimits {
+    fn default() -> Self {
+        Self::from(&BoardDescription::default())
+    }
+}
+
+impl From<&BoardDescription> for SolverLimits {
+    fn from(desc: &BoardDescription) -> Self {
+        // The built-in perfect path fitness scores rooms by their near optimal
+        // routes, without a tier of them every room would score 0
+        let min_tiers = match (&desc.game_mode, desc.fitness_function) {
+            (GameMode::FindPerfectPath, 0) => 1,
+            _ => 0,
+        };
+
+        // Nothing longer than the longest acceptable optimum is worth finding
+        Self {
+            max_solution_depth: desc.max_solution_depth.min(desc.max_optimal_moves).max(0) as usize,
+            near_optimal_tiers: desc.near_optimal_tiers.max(min_tiers) as usize,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 
@@ -23,35 +58,12 @@ pub struct Analysis {
 }
 
 impl Analysis {
-    pub fn compute_fitness(&self, tile_map: &TileMap, board_description: &BoardDescription) -> f32 {
-        if let GameMode::FindPerfectPath = board_description.game_mode {
-            let mut good_route_fitness = self.routes[0][0].prefect_path_fitness(tile_map);
-
-            for analysis in self.routes[0].iter() {
-                good_route_fitness = good_route_fitness.min(analysis.prefect_path_fitness(tile_map))
-            }
-
-            let solution_distribution = (self.routes[1].len() as f32 + self.routes[2].len() as f32)
-                / (1. + self.routes[0].len() as f32);
-
-            let ret = good_route_fitness * solution_distribution;
-
-            ret
-        } else {
-            let mut solution_count = 0.;
-            let mut min_fitness = self.routes[0][0].any_path_fitness(tile_map);
-
-            for same_lenght_routes in self.routes.iter() {
-                for route in same_lenght_routes.iter() {
-                    solution_count += 1.;
-                    min_fitness = min_fitness.min(route.any_path_fitness(tile_map));
-                }
-            }
-
-            let ret = min_fitness / solution_count;
-
-            ret
-        }
+    pub fn compute_fitness(
+        &self,
+        tile_map: &TileMap,
+        board_description: &BoardDescription,
+    ) -> Result<f32, String> {
+        Ok(fitness_function(board_description)?.score(self, tile_map))
     }
 
     pub fn check_still_applies(&self, board: &Board, initial_gate_id: usize) -> bool {
@@ -61,6 +73,19 @@ impl Analysis {
             .all(|route| route.solves(board, initial_gate_id))
     }
 
+    pub fn picks_up_every_key(&self, board: &Board, initial_gate_id: usize) -> bool {
+        let keys = board
+            .map
+            .all_pos()
+            .filter(|p| matches!(board.map.at(p), Tile::Key { .. }))
+            .count();
+
+        keys == 0
+            || self.routes[0]
+                .iter()
+                .all(|route| route.picked_keys(board, initial_gate_id) == keys)
+    }
+
     pub fn print(&self) {
         println!("analisis:");
 
@@ -83,7 +108,7 @@ pub struct Route {
 }
 
 impl Route {
-    pub fn prefect_path_fitness(&self, tile_map: &TileMap) -> f32 {
+    pub fn prefect_path_fitness(&self, tile_map: &TileMap, weights: &FitnessWeights) -> f32 {
         let mut move_sizes = vec![];
 
         for (start, end) in self.solution.iter().tuple_windows() {
@@ -107,42 +132,49 @@ impl Route {
             }
         }
 
-        let mut decision_positions = 0;
-        for (start, end) in self.solution.iter().tuple_windows() {
-            if !tile_map
-                .at(&(start.1 + end.0.right().vector()))
-                .stops_player_during_gameplay()
-                && !tile_map
-                    .at(&(start.1 + end.0.right().vector() * 2))
-                    .stops_player_during_gameplay()
-            {
-                decision_positions += 1;
-            }
-            if !tile_map
-                .at(&(start.1 + end.0.left().vector()))
-                .stops_player_during_gameplay()
-                && !tile_map
-                    .at(&(start.1 + end.0.left().vector() * 2))
-                    .stops_player_during_gameplay()
-            {
-                decision_positions += 1;
-            }
-        }
+        let long_moves = move_sizes
+            .iter()
+            .filter(|e| **e > weights.long_move_threshold)
+            .count();
 
         let positive_factors = [
-            decision_positions as f32 * 100.,
-            move_sizes.iter().filter(|e| **e > 3).count() as f32 * 10.,
-            move_size_mean as f32 * 10.,
-            self.solution.len() as f32,
+            self.decision_positions(tile_map) as f32 * weights.decision_position as f32,
+            long_moves as f32 * weights.long_move as f32,
+            move_size_mean as f32 * weights.move_length as f32,
+            self.solution.len() as f32 * weights.route_length as f32,
+            self.used_stops(tile_map).len() as f32 * weights.used_stop as f32,
         ];
 
-        let negative_factors = [weakwalls_in_the_way as f32, boxes_in_the_way as f32];
+        let negative_factors = [
+            weakwalls_in_the_way as f32 * weights.weak_wall_penalty as f32,
+            boxes_in_the_way as f32 * weights.box_penalty as f32,
+        ];
 
         positive_factors.iter().sum::<f32>() / (negative_factors.iter().sum::<f32>() + 1.)
     }
 
-    pub fn any_path_fitness(&self, tile_map: &TileMap) -> f32 {
+    pub fn any_path_fitness(&self, tile_map: &TileMap, weights: &FitnessWeights) -> f32 {
+        let positive_factors = [
+            self.decision_positions(tile_map) as f32 * weights.decision_position as f32,
+            self.solution.len() as f32 * weights.route_length as f32,
+            self.used_stops(tile_map).len() as f32 * weights.used_stop as f32,
+        ];
 
+        positive_factors.iter().sum::<f32>()
+    }
+
+    // Stops the route ends a move on, the only way a stop does anything
+    pub fn used_stops(&self, tile_map: &TileMap) -> Vec<Pos> {
+        self.solution
+            .iter()
+            .map(|(_, p)| *p)
+            .filter(|p| tile_map.at(p) == Tile::Stop)
+            .unique()
+            .collect()
+    }
+
+    // Places along the route where the player could have turned into open space
+    fn decision_positions(&self, tile_map: &TileMap) -> usize {
         let mut decision_positions = 0;
         for (start, end) in self.solution.iter().tuple_windows() {
             if !tile_map
@@ -165,279 +197,578 @@ impl Route {
             }
         }
 
-        let positive_factors = [
-            decision_positions as f32 * 100.,
-            self.solution.len() as f32,
-        ];
+        decision_positions
+    }
 
+    fn picked_keys(&self, board: &Board, initial_gate_id: usize) -> usize {
+        let mut board = board.clone();
+        let mut position = board.get_gate_position(initial_gate_id);
+        let mut ret = 0;
 
-        positive_factors.iter().sum::<f32>()
+        for (direction, _) in self.solution.iter() {
+            let new_step = step(&board.map, &position, direction);
+
+            ret += board
+                .resolve_step(&new_step, direction)
+                .iter()
+                .filter(|change| matches!(change, BoardChange::PickedKey { .. }))
+                .count();
+            position = new_step.pos;
+        }
+
+        ret
     }
 
     fn solves(&self, board: &Board, initial_gate_id: usize) -> bool {
-        // println!("-----");
-        // println!("check if {self:?} solves ");
-
-        // board.print(vec![]);
-
-        let mut cursor = SearchState {
-            tile_length: 0,
-            path: Rc::from(PathNode::Root {
-                root_direction: board.get_gate_direction(initial_gate_id),
-                root_position: board.get_gate_position(initial_gate_id),
-            }),
-            path_len: 0,
-            visitations: Visitations::new(board.get_width(), board.get_height()),
-            board: Rc::from(board.clone()),
-            broken_walls: 0,
-            hitted_boxes: 0,
-        };
+        let mut board = board.clone();
+        let mut position = board.get_gate_position(initial_gate_id);
+        let mut visitations = Visitations::new(board.get_width(), board.get_height());
 
         for (direction, _) in self.solution.iter() {
-            // println!("moving {:?}", board.get_gate_direction(initial_gate_id));
+            let new_step = step(&board.map, &position, direction);
 
-            cursor = match cursor.step(direction) {
-                Ok(new_cursor) => {
-                    // println!("new cursor is {new_cursor:?}");
+            if new_step.changes_board() {
+                board.resolve_step(&new_step, direction);
+                visitations = Visitations::new(board.get_width(), board.get_height());
+            }
 
-                    new_cursor
-                }
-                Err(_) => {
-                    return false;
-                }
-            };
-        }
+            if visitations.contains(&new_step.pos) {
+                return false;
+            }
 
-        if self.solution.last().unwrap().1 != cursor.path.get_position() {
-            // println!("position desync {pos:?} {:?}", cursor.path.get_position());
-            return false;
+            visitations.insert(&new_step.pos);
+            position = new_step.pos;
         }
 
-        true
+        self.solution
+            .last()
+            .is_some_and(|(_, end)| *end == position)
     }
 }
 
-#[derive(Debug)]
-enum PathNode {
-    Node {
-        board_change: bool,
-        direction: Direction,
-        position: Pos,
-        last_move: Rc<PathNode>,
-    },
-    Root {
-        root_direction: Direction,
-        root_position: Pos,
-    },
-}
-
-impl PathNode {
-    fn into_vector(&self) -> Vec<(Direction, Pos)> {
-        match &self {
-            Self::Node {
-                direction,
-                position,
-                last_move,
-                ..
-            } => {
-                let mut ret = last_move.into_vector();
-                ret.push((*direction, *position));
-                ret
-            }
-            Self::Root { .. } => vec![],
+// Keys are picked up on the way, so a lock further along the same move is
+// already open by the time the player gets to it. A move that would go
+// through the same portal twice stops on it instead of looping forever.
+// Standing on an arrow only the arrow's direction goes anywhere. Cracked ice
+// breaks behind the player, so it's only in `passed` once they left it.
+pub fn step(map: &TileMap, start: &Pos, direction: &Direction) -> StepResult {
+    let mut ret = start.clone();
+    let mut passed = vec![];
+    let mut keys = vec![];
+    let mut teleports: Vec<(Pos, Pos)> = vec![];
+
+    if let Tile::Arrow(forced) = map.at(start) {
+        if forced != *direction {
+            return StepResult {
+                hit: Tile::Arrow(forced),
+                hit_pos: ret,
+                pos: ret,
+                passed,
+                teleports,
+            };
         }
     }
 
-    fn get_position(&self) -> Pos {
-        match self {
-            PathNode::Node { position, .. } => *position,
-            PathNode::Root { root_position, .. } => *root_position,
+    loop {
+        let next = map.at(&(ret + direction.vector()));
+
+        let blocked = match &next {
+            Tile::Lock { key: Some(key) } => !keys.contains(key),
+            Tile::CrackedIce => passed.contains(&(ret + direction.vector())),
+            tile => tile.stops_player_during_sim(),
+        };
+
+        if blocked {
+            break;
         }
-    }
 
-    fn next_posible_directions(&self) -> Vec<Direction> {
-        match self {
-            PathNode::Node {
-                board_change,
-                direction,
-                ..
-            } => {
-                if *board_change {
-                    Direction::all()
-                } else {
-                    vec![direction.left(), direction.right()]
+        if ret == *start && map.at(start) == Tile::CrackedIce && !passed.contains(start) {
+            passed.push(*start);
+        }
+
+        ret = ret + direction.vector();
+
+        if next.changes_board_on_pass() {
+            passed.push(ret);
+        }
+
+        match &next {
+            Tile::Stop | Tile::Arrow(_) => {
+                return StepResult {
+                    hit: next.clone(),
+                    hit_pos: ret,
+                    pos: ret,
+                    passed,
+                    teleports,
+                }
+            }
+            Tile::Key { id } => keys.push(*id),
+            Tile::Portal { .. } => {
+                if teleports.iter().any(|(from, _)| *from == ret) {
+                    break;
+                }
+
+                if let Some(twin) = map.portal_twin(&ret) {
+                    teleports.push((ret, twin));
+                    ret = twin;
                 }
             }
-            PathNode::Root { root_direction, .. } => vec![*root_direction],
+            _ => {}
         }
     }
-}
 
-pub fn step(map: &TileMap, start: &Pos, direction: &Direction) -> StepResult {
-    let mut ret = start.clone();
-
-    while !map
-        .at(&(ret + direction.vector()))
-        .stops_player_during_sim()
-    {
-        ret = ret + direction.vector();
+    if map.at(&ret) == Tile::CrackedIce {
+        passed.retain(|p| *p != ret);
     }
 
     StepResult {
         hit: map.at(&(ret + direction.vector())),
         hit_pos: ret + direction.vector(),
         pos: ret,
+        passed,
+        teleports,
     }
 }
 
-#[derive(Debug)]
-struct SearchState {
-    board: Rc<Board>,
-    tile_length: isize,
-    path: Rc<PathNode>,
-    path_len: usize,
-    visitations: Visitations,
-    broken_walls: usize,
-    hitted_boxes: usize,
+pub struct StepResult {
+    pub hit: Tile,
+    pub hit_pos: Pos,
+    pub pos: Pos,
+    // Tiles that change once the player went over them, in the order passed
+    pub passed: Vec<Pos>,
+    // Every portal the player went into and the twin they came out of
+    pub teleports: Vec<(Pos, Pos)>,
 }
 
-impl SearchState {
-    fn step(&self, direction: &Direction) -> Result<Self, String> {
-        let step_start = self.path.get_position();
-
-        let new_step = step(&self.board.map, &step_start, direction);
-        let step_length =
-            (new_step.pos.x - step_start.x).abs() + (new_step.pos.y - step_start.y).abs();
-
-        let mut new_board = Rc::clone(&self.board);
-        let mut new_board_changed = false;
-        let mut new_hitted_boxes = self.hitted_boxes;
-        let mut new_visitations = self.visitations.clone();
-
-        if let Tile::WeakWall = new_step.hit {
-            Rc::make_mut(&mut new_board)
-                .map
-                .set(&new_step.hit_pos, Tile::Ice);
-            new_hitted_boxes += 1;
-            new_visitations = Visitations::new(self.board.get_width(), self.board.get_height());
-            new_board_changed = true;
+impl StepResult {
+    pub fn changes_board(&self) -> bool {
+        self.hit.changes_board_on_hit() || !self.passed.is_empty()
+    }
+}
+
+pub fn analyze(
+    initial_board: &Board,
+    entry_gate_id: usize,
+    exit_gate_id: usize,
+    limits: &SolverLimits,
+) -> Result<Analysis, String> {
+    search(
+        initial_board,
+        initial_board.get_gate_position(entry_gate_id),
+        vec![initial_board.get_gate_direction(entry_gate_id)],
+        vec![initial_board.get_gate_position(exit_gate_id)],
+        limits,
+    )
+}
+
+/// Solves `board` as it is, boxes and weak walls included, for a player standing
+/// at `start`. With no `target_gate_ids` every exit gate counts as a way out.
+pub fn analyze_from(
+    board: &Board,
+    start: Pos,
+    target_gate_ids: Option<Vec<usize>>,
+    limits: &SolverLimits,
+) -> Result<Analysis, String> {
+    let targets = match target_gate_ids {
+        Some(ids) => ids.iter().map(|id| board.get_gate_position(*id)).collect(),
+        None => board
+            .gates
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| board.get_gate_destination(*id).is_some())
+            .map(|(_, gate)| gate.pos)
+            .collect(),
+    };
+
+    search(board, start, Direction::all(), targets, limits)
+}
+
+fn search(
+    initial_board: &Board,
+    start: Pos,
+    first_directions: Vec<Direction>,
+    targets: Vec<Pos>,
+    limits: &SolverLimits,
+) -> Result<Analysis, String> {
+    let mut space = StateSpace::new(
+        initial_board,
+        start,
+        first_directions.clone(),
+        targets,
+        limits.max_solution_depth,
+    );
+
+    // The state space ignores the no-revisit rule of a route, so its distances
+    // are only a lower bound and the real optimum may be a few moves longer.
+    let mut best_movement_count = match space.first_goal_depth() {
+        Some(depth) => depth,
+        None => return Err(String::from("Unsolvable room")),
+    };
+
+    while best_movement_count <= limits.max_solution_depth {
+        let deepest = best_movement_count + limits.near_optimal_tiers;
+        space.expand_to(deepest);
+
+        let distances = space.distances_to_goal();
+
+        let mut visitations = space.no_visitations.clone();
+        visitations.insert(&start);
+
+        let mut found = vec![];
+        space.enumerate(
+            0,
+            &first_directions,
+            &mut visitations,
+            &mut vec![],
+            deepest,
+            &distances,
+            &mut found,
+        );
+
+        match found.iter().map(|route| route.len()).min() {
+            Some(movement_count) if movement_count == best_movement_count => {
+                let mut solution_states = vec![vec![]; limits.near_optimal_tiers + 1];
+
+                for solution in found {
+                    solution_states[solution.len() - best_movement_count].push(Route { solution });
+                }
+
+                return Ok(Analysis {
+                    optimal_movement_count: best_movement_count,
+                    routes: solution_states,
+                });
+            }
+            Some(movement_count) => best_movement_count = movement_count,
+            None => best_movement_count += limits.near_optimal_tiers + 1,
         }
+    }
 
-        let mut new_broken_walls = self.broken_walls;
+    Err(String::from("Unsolvable room"))
+}
 
-        if let Tile::Box = new_step.hit {
-            Rc::make_mut(&mut new_board).box_cascade(&new_step.hit_pos, direction);
-            new_broken_walls += 1;
-            new_visitations = Visitations::new(self.board.get_width(), self.board.get_height());
-            new_board_changed = true;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Heading {
+    Root,
+    Free,
+    Horizontal,
+    Vertical,
+    Forced(Direction),
+}
+
+impl Heading {
+    // Stops and arrows never block a move, so hitting one means standing on it
+    fn after(step: &StepResult, direction: &Direction, free: bool) -> Self {
+        match (&step.hit, direction) {
+            (Tile::Arrow(forced), _) => Heading::Forced(*forced),
+            (Tile::Stop, _) => Heading::Free,
+            _ if free => Heading::Free,
+            (_, Direction::East | Direction::West) => Heading::Horizontal,
+            (_, Direction::North | Direction::South) => Heading::Vertical,
         }
+    }
 
-        if new_visitations.contains(&new_step.pos) {
-            return Err(format!("Went into a loop at {:?}", &new_step.pos));
+    fn next_posible_directions(&self, last: &Direction) -> Vec<Direction> {
+        match self {
+            Heading::Root | Heading::Free => Direction::all(),
+            Heading::Forced(forced) => vec![*forced],
+            _ => vec![last.left(), last.right()],
         }
+    }
 
-        let new_path = PathNode::Node {
-            direction: direction.to_owned(),
-            position: new_step.pos,
-            last_move: self.path.clone(),
-            board_change: new_board_changed,
+    // Arrows never turn during a room, so a forced heading can share its
+    // slot with the free one
+    fn slot(&self) -> usize {
+        match self {
+            Heading::Root => 0,
+            Heading::Free | Heading::Forced(_) => 1,
+            Heading::Horizontal => 2,
+            Heading::Vertical => 3,
+        }
+    }
+}
+
+fn direction_index(direction: &Direction) -> usize {
+    match direction {
+        Direction::North => 0,
+        Direction::East => 1,
+        Direction::South => 2,
+        Direction::West => 3,
+    }
+}
+
+// Only weak walls, boxes, keys and cracked ice ever change during a room, so
+// they alone tell two versions of a board apart. Locks open with their key,
+// so the keys left on the board also tell which locks are still closed.
+fn mutation_key(map: &TileMap) -> Vec<u128> {
+    let tile_count = (map.get_width() * map.get_height()) as usize;
+    let mut ret = vec![0; (tile_count * 4) / 128 + 1];
+
+    for (i, tile) in map.0.iter().flatten().enumerate() {
+        let layer = match tile {
+            Tile::Box => 0,
+            Tile::WeakWall => 1,
+            Tile::Key { .. } => 2,
+            Tile::CrackedIce => 3,
+            _ => continue,
         };
+        let bit = i + layer * tile_count;
 
-        new_visitations.insert(&new_step.pos);
-
-        Ok(SearchState {
-            tile_length: self.tile_length + step_length,
-            path: Rc::from(new_path),
-            path_len: self.path_len + 1,
-            visitations: new_visitations,
-            board: new_board,
-            broken_walls: new_broken_walls,
-            hitted_boxes: new_hitted_boxes,
-        })
+        ret[bit / 128] |= 1 << (bit % 128);
     }
+
+    ret
 }
 
-pub struct StepResult {
-    pub hit: Tile,
-    pub hit_pos: Pos,
-    pub pos: Pos,
+#[derive(Clone, Copy)]
+struct Edge {
+    to: usize,
+    board_change: bool,
 }
 
-pub fn analyze(
-    initial_board: &Board,
-    entry_gate_id: usize,
-    exit_gate_id: usize,
-) -> Result<Analysis, String> {
-    // board.print(vec![]);
-
-    let mut initial_visitations =
-        Visitations::new(initial_board.get_width(), initial_board.get_height());
-    initial_visitations.insert(&initial_board.get_gate_position(entry_gate_id));
-
-    let mut states = VecDeque::from([SearchState {
-        tile_length: 0,
-        path: Rc::from(PathNode::Root {
-            root_direction: initial_board.get_gate_direction(entry_gate_id),
-            root_position: initial_board.get_gate_position(entry_gate_id),
-        }),
-        path_len: 0,
-        visitations: initial_visitations,
-        board: Rc::from(initial_board.clone()),
-        broken_walls: 0,
-        hitted_boxes: 0,
-    }]);
-
-    let mut solution_states = vec![vec![]; EXTRA_MOVES_SEARCH_MARGIN];
-
-    let mut best_movement_count = None;
-
-    while let Some(state) = states.pop_front() {
-        // println!("considering {:?}", state.path.into_vector());
-        for dir in state.path.next_posible_directions() {
-            let new_state = state.step(&dir);
-
-            let new_state = if let Ok(new_state) = new_state {
-                new_state
-            } else {
-                // println!("{:?} going to {dir:?}", new_state);
-                continue;
-            };
+struct State {
+    position: Pos,
+    heading: Heading,
+    edges: [Option<Edge>; 4],
+}
+
+// Every distinct (position, board, heading) reachable from the start, explored
+// breadth first one move at a time. Boards are interned and each one gets a
+// dense table of state ids, so only board changes need hashing.
+struct StateSpace {
+    targets: Vec<Pos>,
+    max_depth: usize,
+    root_directions: Vec<Direction>,
+    no_visitations: Visitations,
+    width: isize,
+    boards: HashMap<Vec<u128>, usize>,
+    ids: Vec<Vec<usize>>,
+    states: Vec<State>,
+    goals: Vec<usize>,
+    frontier: Vec<(usize, Rc<Board>, usize, Direction)>,
+    depth: usize,
+}
+
+const NO_STATE: usize = usize::MAX;
+
+impl StateSpace {
+    fn new(
+        board: &Board,
+        start: Pos,
+        root_directions: Vec<Direction>,
+        targets: Vec<Pos>,
+        max_depth: usize,
+    ) -> Self {
+        let mut ret = Self {
+            targets,
+            max_depth,
+            root_directions,
+            no_visitations: Visitations::new(board.get_width(), board.get_height()),
+            width: board.get_width(),
+            boards: HashMap::from([(mutation_key(&board.map), 0)]),
+            ids: vec![vec![
+                NO_STATE;
+                (board.get_width() * board.get_height()) as usize * 4
+            ]],
+            states: vec![State {
+                position: start,
+                heading: Heading::Root,
+                edges: [None; 4],
+            }],
+            goals: vec![],
+            frontier: vec![(0, Rc::from(board.clone()), 0, Direction::North)],
+            depth: 0,
+        };
+
+        let index = ret.index(&start, Heading::Root);
+        ret.ids[0][index] = 0;
+        ret
+    }
 
-            if new_state.path.get_position() == state.board.get_gate_position(exit_gate_id) {
-                let best_movement_count = if let Some(best_movement_count) = best_movement_count {
-                    best_movement_count
-                } else {
-                    best_movement_count = Some(new_state.path_len);
+    fn index(&self, p: &Pos, heading: Heading) -> usize {
+        (p.x + p.y * self.width) as usize * 4 + heading.slot()
+    }
+
+    fn board_id(&mut self, map: &TileMap) -> usize {
+        let key = mutation_key(map);
+
+        if let Some(board_id) = self.boards.get(&key) {
+            return *board_id;
+        }
+
+        let board_id = self.ids.len();
+        self.boards.insert(key, board_id);
+        self.ids.push(vec![NO_STATE; self.ids[0].len()]);
+        board_id
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.frontier.is_empty()
+    }
+
+    fn first_goal_depth(&mut self) -> Option<usize> {
+        while self.goals.is_empty() && !self.is_exhausted() && self.depth < self.max_depth {
+            self.expand_to(self.depth + 1);
+        }
+
+        if self.goals.is_empty() {
+            None
+        } else {
+            Some(self.depth)
+        }
+    }
 
-                    new_state.path_len
+    fn expand_to(&mut self, depth: usize) {
+        while self.depth < depth && !self.is_exhausted() {
+            let mut next_frontier = vec![];
+
+            for (id, board, board_id, last_direction) in std::mem::take(&mut self.frontier) {
+                let position = self.states[id].position;
+
+                let directions = match self.states[id].heading {
+                    Heading::Root => self.root_directions.clone(),
+                    heading => heading.next_posible_directions(&last_direction),
                 };
 
-                solution_states[new_state.path_len - best_movement_count]
-                    .push(state2analysis(new_state));
-            } else {
-                if let Some(best_movement_count) = best_movement_count {
-                    if new_state.path_len < (best_movement_count + EXTRA_MOVES_SEARCH_MARGIN - 1) {
-                        states.push_back(new_state);
+                for direction in directions {
+                    let new_step = step(&board.map, &position, &direction);
+                    let board_change = new_step.changes_board();
+
+                    if !board_change && new_step.pos == position {
+                        continue;
+                    }
+
+                    let mut new_board = Rc::clone(&board);
+                    let mut new_board_id = board_id;
+
+                    if board_change {
+                        Rc::make_mut(&mut new_board).resolve_step(&new_step, &direction);
+                        new_board_id = self.board_id(&new_board.map);
                     }
-                } else if TOP_SOLUTION_SIZE > new_state.path_len {
-                    states.push_back(new_state);
+
+                    // Going back after a teleport doesn't retrace the move
+                    let heading = Heading::after(
+                        &new_step,
+                        &direction,
+                        board_change || !new_step.teleports.is_empty(),
+                    );
+                    let index = self.index(&new_step.pos, heading);
+
+                    let mut to = self.ids[new_board_id][index];
+
+                    if to == NO_STATE {
+                        to = self.states.len();
+                        self.ids[new_board_id][index] = to;
+                        self.states.push(State {
+                            position: new_step.pos,
+                            heading,
+                            edges: [None; 4],
+                        });
+
+                        if self.targets.contains(&new_step.pos) {
+                            self.goals.push(to);
+                        } else {
+                            next_frontier.push((to, new_board, new_board_id, direction));
+                        }
+                    }
+
+                    self.states[id].edges[direction_index(&direction)] =
+                        Some(Edge { to, board_change });
                 }
             }
+
+            self.frontier = next_frontier;
+            self.depth += 1;
         }
     }
 
-    if let Some(max_movement) = best_movement_count {
-        Ok(Analysis {
-            optimal_movement_count: max_movement,
-            routes: solution_states,
-        })
-    } else {
-        Err(String::from("Unsolvable room"))
+    fn distances_to_goal(&self) -> Vec<usize> {
+        let mut reverse = vec![vec![]; self.states.len()];
+        for (from, state) in self.states.iter().enumerate() {
+            for edge in state.edges.iter().flatten() {
+                reverse[edge.to].push(from);
+            }
+        }
+
+        let mut distances = vec![usize::MAX; self.states.len()];
+        let mut pending = VecDeque::new();
+
+        for goal in &self.goals {
+            distances[*goal] = 0;
+            pending.push_back(*goal);
+        }
+
+        while let Some(id) = pending.pop_front() {
+            for from in &reverse[id] {
+                if distances[*from] == usize::MAX {
+                    distances[*from] = distances[id] + 1;
+                    pending.push_back(*from);
+                }
+            }
+        }
+
+        distances
     }
-}
 
-fn state2analysis(state: SearchState) -> Route {
-    Route {
-        solution: state.path.into_vector(),
+    // Walks every route of at most `deepest` moves that never revisits a
+    // position between board changes, in the same direction order as the
+    // original breadth first search so equally long routes keep their order.
+    // Visitations are undone on the way back up instead of copied at each move.
+    #[allow(clippy::too_many_arguments)]
+    fn enumerate(
+        &self,
+        id: usize,
+        directions: &[Direction],
+        visitations: &mut Visitations,
+        path: &mut Vec<(Direction, Pos)>,
+        deepest: usize,
+        distances: &[usize],
+        found: &mut Vec<Vec<(Direction, Pos)>>,
+    ) {
+        for direction in directions {
+            let edge = match self.states[id].edges[direction_index(direction)] {
+                Some(edge) => edge,
+                None => continue,
+            };
+
+            if (path.len() + 1).saturating_add(distances[edge.to]) > deepest {
+                continue;
+            }
+
+            let next = &self.states[edge.to];
+
+            // A board change starts a fresh set, the current one comes back
+            // once this branch is done
+            let before_change = if edge.board_change {
+                Some(std::mem::replace(visitations, self.no_visitations.clone()))
+            } else if visitations.contains(&next.position) {
+                continue;
+            } else {
+                None
+            };
+            visitations.insert(&next.position);
+
+            path.push((*direction, next.position));
+
+            if self.targets.contains(&next.position) {
+                found.push(path.clone());
+            } else {
+                self.enumerate(
+                    edge.to,
+                    &next.heading.next_posible_directions(direction),
+                    visitations,
+                    path,
+                    deepest,
+                    distances,
+                    found,
+                );
+            }
+
+            path.pop();
+
+            match before_change {
+                Some(before_change) => *visitations = before_change,
+                None => visitations.remove(&next.position),
+            }
+        }
     }
 }
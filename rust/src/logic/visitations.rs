@@ -20,4 +20,10 @@ impl Visitations {
 
         self.0[(index / 128) as usize] |= 0x1 << (index % 128);
     }
+
+    pub fn remove(&mut self, p: &Pos) {
+        let index = p.x + p.y * self.1;
+
+        self.0[(index / 128) as usize] &= !(0x1 << (index % 128));
+    }
 }
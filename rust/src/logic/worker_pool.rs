@@ -1,37 +1,51 @@
 use std::{
+    collections::VecDeque,
     sync::{
-        mpsc::{self},
-        Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError, TryRecvError},
+        Arc, Condvar, Mutex,
     },
-    thread::{self, available_parallelism, spawn, JoinHandle},
-    time::{self},
+    thread::{available_parallelism, spawn, JoinHandle},
+    time::{Duration, Instant},
 };
 
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use sorted_vec::partial::{SortedSet};
 
 use crate::{
     api::{
         board_description::BoardDescription,
         dart_board::DartBoard,
+        difficulty::DifficultyReport,
         direction::Direction,
-        main::{AutoGenOutput, LeftRotatable},
+        generator::{GeneratorConfig, GeneratorEvent, GeneratorState, GeneratorStatus},
+        main::{AutoGenOutput, BestEffortOutput, LeftRotatable},
     },
+    frb_generated::StreamSink,
     logic::{
         board::Board,
-        noise_reduction::asthetic_cleanup,
-        solver::{analyze, Analysis},
+        codec::{read_file, write_file, Codec, Reader, Writer},
+        fitness::{fitness_function, FitnessFunction},
+        level_pack::{LevelPack, PackedBoard},
+        noise_reduction::{asthetic_cleanup, strip_unused_stops},
+        solver::{analyze, Analysis, SolverLimits},
     },
 };
 
 pub enum CtrlMsg {
     Kill,
     Halt(usize),
+    Pause,
+    Resume,
 }
 
+#[derive(Clone)]
 struct Candidate {
     pub fitness: f32,
     pub board: Board,
     pub analysis: Analysis,
+    pub difficulty: Option<DifficultyReport>,
+    pub seed: u64,
 }
 
 impl std::fmt::Debug for Candidate {
@@ -54,178 +68,1091 @@ impl PartialEq for Candidate {
     }
 }
 
+// The difficulty is left out, it is recomputed when the board is served
+impl Codec for Candidate {
+    fn write(&self, w: &mut Writer) {
+        w.put(&self.fitness)
+            .put(&self.seed)
+            .put(&self.board)
+            .put(&self.analysis);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        Ok(Candidate {
+            fitness: r.get()?,
+            seed: r.get()?,
+            board: r.get()?,
+            analysis: r.get()?,
+            difficulty: None,
+        })
+    }
+}
+
+impl From<PackedBoard> for Candidate {
+    fn from(value: PackedBoard) -> Self {
+        Candidate {
+            fitness: value.fitness,
+            board: value.board,
+            analysis: value.analysis,
+            difficulty: None,
+            seed: value.seed,
+        }
+    }
+}
+
 struct Worker {
     crtl_channel: mpsc::Sender<CtrlMsg>,
     join: JoinHandle<()>,
 }
 
-static G_WORKER: Mutex<Option<Vec<Worker>>> = Mutex::new(None);
-static G_RESULT_QUEUE: Mutex<SortedSet<Candidate>> = Mutex::new(SortedSet::new());
-static G_BOARD_DESC: Mutex<Option<BoardDescription>> = Mutex::new(None);
-static G_RESULT_MAX_SIZE: Mutex<usize> = Mutex::new(0);
+#[derive(Default)]
+struct WorkerStats {
+    iterations: AtomicUsize,
+    candidates: AtomicUsize,
+    solvable: AtomicUsize,
+}
 
-fn submit(candidate: Candidate) -> f32 {
-    // println!("submiting a candidate with fitness {}", candidate.fitness);
-    let mut result = G_RESULT_QUEUE.lock().unwrap();
+// Rooms tried when nothing at all was found before the deadline, for as long
+// as the deadline plus the allowance permit
+const FALLBACK_ATTEMPTS: usize = 200;
+const FALLBACK_ALLOWANCE: Duration = Duration::from_millis(100);
 
-    result.insert(candidate);
+const BUFFER_MAGIC: &[u8; 4] = b"ICEB";
+const BUFFER_VERSION: u64 = 6;
 
-    let mut ret = 0.;
+// The path doubles as the lock that keeps two writers off the file
+struct BufferFile {
+    path: Mutex<String>,
+    board_desc: BoardDescription,
+}
 
-    if result.len() != 0 {
-        ret = result[0].fitness;
-    }
+struct ResultQueue {
+    candidates: Mutex<SortedSet<Candidate>>,
+    packed: Mutex<VecDeque<Candidate>>,
+    ready: Condvar,
+    best_rejected: Mutex<Option<Candidate>>,
+    max_size: Mutex<usize>,
+    sinks: Mutex<Vec<StreamSink<GeneratorEvent>>>,
+    state: Mutex<GeneratorState>,
+    stagnant_iterations: AtomicUsize,
+    live_workers: AtomicUsize,
+    buffer_file: Option<BufferFile>,
+}
+
+impl ResultQueue {
+    fn submit(&self, candidate: Candidate) -> f32 {
+        // println!("submiting a candidate with fitness {}", candidate.fitness);
+
+        // Killed workers still finish the candidate they were on, by then the
+        // buffer file may already belong to a newer pool
+        if *self.state.lock().unwrap() == GeneratorState::Finished {
+            return candidate.fitness;
+        }
+
+        let mut result = self.candidates.lock().unwrap();
+
+        let previous_threshold = result.first().map(|e| e.fitness);
+        let fitness = candidate.fitness;
+        let accepted = result.len() < *self.max_size.lock().unwrap()
+            || previous_threshold.is_some_and(|previous| fitness > previous);
+
+        result.insert(candidate);
+
+        let mut ret = 0.;
 
-    while result.len() > *(G_RESULT_MAX_SIZE.lock().unwrap()) {
-        result.remove_index(0);
         if result.len() != 0 {
             ret = result[0].fitness;
         }
+
+        while result.len() > *(self.max_size.lock().unwrap()) {
+            result.remove_index(0);
+            if result.len() != 0 {
+                ret = result[0].fitness;
+            }
+        }
+
+        // println!("  >  new fitness goal is {ret} {result:?}");
+
+        self.ready.notify_all();
+
+        let buffered_boards = result.len();
+        drop(result);
+
+        if accepted {
+            self.stagnant_iterations.store(0, Ordering::Relaxed);
+            self.persist_or_log();
+            self.emit(GeneratorEvent::BoardAccepted {
+                fitness: fitness as f64,
+                buffered_boards: buffered_boards as isize,
+            });
+        }
+
+        if previous_threshold.is_some_and(|previous| ret > previous) {
+            self.emit(GeneratorEvent::FitnessThresholdImproved {
+                fitness_threshold: ret as f64,
+            });
+        }
+
+        ret
+    }
+
+    // Sinks whose Dart stream was closed are dropped on the first failed send
+    fn emit(&self, event: GeneratorEvent) {
+        self.sinks
+            .lock()
+            .unwrap()
+            .retain(|sink| sink.add(event.clone()).is_ok());
+    }
+
+    // Keeps the best candidate that didn't beat its worker's fitness filter,
+    // to be served when nothing better shows up in time
+    fn reject(&self, candidate: Candidate) {
+        let mut best_rejected = self.best_rejected.lock().unwrap();
+
+        if best_rejected
+            .as_ref()
+            .is_none_or(|best| candidate.fitness > best.fitness)
+        {
+            *best_rejected = Some(candidate);
+        }
     }
 
-    // println!("  >  new fitness goal is {ret} {result:?}");
+    // Buffered boards are kept for the next pool of the same description. The
+    // file is rewritten whenever a board is accepted or served, so it survives
+    // the app being killed and served boards don't come back.
+    fn persist(&self) -> Result<(), String> {
+        let Some(buffer_file) = &self.buffer_file else {
+            return Ok(());
+        };
+
+        let path = buffer_file.path.lock().unwrap();
+        let candidates = self.candidates.lock().unwrap().to_vec();
 
-    ret
+        write_file(
+            &path,
+            BUFFER_MAGIC,
+            BUFFER_VERSION,
+            &(buffer_file.board_desc.clone(), candidates),
+        )
+    }
+
+    fn persist_or_log(&self) {
+        if let Err(e) = self.persist() {
+            println!("couldnt persist boards: {e}");
+        }
+    }
+
+    // Iterations only count as stagnant once the buffer is full, a slow
+    // description still filling it is left to the time budget. The count
+    // starts over whenever a board is accepted or taken.
+    fn is_stagnant(&self, stagnation_limit: Option<isize>) -> bool {
+        if self.candidates.lock().unwrap().len() < *self.max_size.lock().unwrap() {
+            self.stagnant_iterations.store(0, Ordering::Relaxed);
+            return false;
+        }
+
+        let stagnant_iterations = self.stagnant_iterations.fetch_add(1, Ordering::Relaxed) + 1;
+
+        stagnation_limit.is_some_and(|limit| stagnant_iterations >= limit as usize)
+    }
+
+    // Returns false if the pool had already finished
+    fn finish(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if *state == GeneratorState::Finished {
+            return false;
+        }
+
+        *state = GeneratorState::Finished;
+        self.ready.notify_all();
+
+        // Dropping the sinks closes the streams on the Dart side
+        self.emit(GeneratorEvent::Finished);
+        self.sinks.lock().unwrap().clear();
+
+        true
+    }
 }
 
-pub fn get_new_room(entry_direction: Direction) -> AutoGenOutput {
-    let mut ret = G_RESULT_QUEUE.lock().unwrap();
+// Each pool searches a single description and owns its workers and buffer, so
+// several can run side by side.
+pub struct WorkerPool {
+    board_desc: BoardDescription,
+    queue: Arc<ResultQueue>,
+    workers: Mutex<Vec<Worker>>,
+    stats: Vec<Arc<WorkerStats>>,
+}
+
+impl WorkerPool {
+    pub fn start(
+        board_desc: BoardDescription,
+        max_buffered_boards: isize,
+        seed: Option<u64>,
+        mut config: GeneratorConfig,
+    ) -> Result<Self, String> {
+        // Taken out so the workers don't each get a copy of the whole pack
+        let packed = match config.level_pack.take() {
+            Some(bytes) => match LevelPack::from_bytes(&bytes) {
+                Ok(pack) => pack.boards_for(&board_desc),
+                Err(e) => {
+                    println!("couldnt read level pack: {e}");
+                    vec![]
+                }
+            },
+            None => vec![],
+        };
+
+        // Half the cores by default, but at least one worker on single core
+        // machines, and none if the pack already has every board
+        let paralelism = match config.thread_count {
+            _ if packed.len() >= max_buffered_boards.max(0) as usize => 0,
+            Some(thread_count) => thread_count.max(1) as usize,
+            None => (available_parallelism()
+                .expect("couldnt get available parallelism")
+                .get()
+                / 2)
+            .max(1),
+        };
+        // let paralelism = 1;
+
+        // Looked up before any worker starts, so an unknown fitness function
+        // id fails here instead of leaving workers behind
+        let fitness_functions = (0..paralelism)
+            .map(|_| fitness_function(&board_desc))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let buffer_path = config
+            .buffer_dir
+            .as_ref()
+            .map(|dir| buffer_path(dir, &board_desc));
+
+        let queue = Arc::new(ResultQueue {
+            candidates: Mutex::new(SortedSet::new()),
+            packed: Mutex::new(packed.into_iter().map(Candidate::from).collect()),
+            ready: Condvar::new(),
+            best_rejected: Mutex::new(None),
+            max_size: Mutex::new(max_buffered_boards as usize),
+            sinks: Mutex::new(vec![]),
+            state: Mutex::new(GeneratorState::Running),
+            stagnant_iterations: AtomicUsize::new(0),
+            live_workers: AtomicUsize::new(paralelism),
+            buffer_file: buffer_path.clone().map(|path| BufferFile {
+                path: Mutex::new(path),
+                board_desc: board_desc.clone(),
+            }),
+        });
+
+        if let Some(path) = &buffer_path {
+            match restore(path, &board_desc) {
+                Ok(restored) => {
+                    let mut candidates = queue.candidates.lock().unwrap();
+
+                    for candidate in restored {
+                        candidates.insert(candidate);
+                    }
 
-    match ret.pop() {
-        None => {
-            if *G_RESULT_MAX_SIZE.lock().unwrap() == 0 {
-                AutoGenOutput::NoMoreBufferedBoards
-            } else {
-                AutoGenOutput::NotReady
+                    while candidates.len() > max_buffered_boards.max(0) as usize {
+                        candidates.remove_index(0);
+                    }
+
+                    println!("restored {} boards from {path}", candidates.len());
+                }
+                Err(e) => println!("couldnt restore boards: {e}"),
             }
         }
 
-        Some(candidate) => {
-            let mut max_size = G_RESULT_MAX_SIZE.lock().unwrap();
+        let deadline = config
+            .time_budget_millis
+            .map(|millis| Instant::now() + Duration::from_millis(millis.max(0) as u64));
+
+        let stats = (0..paralelism)
+            .map(|_| Arc::new(WorkerStats::default()))
+            .collect::<Vec<_>>();
+
+        let workers = stats
+            .iter()
+            .zip(fitness_functions)
+            .enumerate()
+            .map(|(worker_id, (stats, fitness_function))| {
+                let (ctrl_tx, ctrl_rx) = mpsc::channel();
+
+                let board_desc = board_desc.clone();
+                let queue = queue.clone();
+                let stats = stats.clone();
+                let config = config.clone();
+                let rng = match seed {
+                    Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(worker_id as u64)),
+                    None => StdRng::from_os_rng(),
+                };
+
+                Worker {
+                    crtl_channel: ctrl_tx,
+                    join: spawn(move || {
+                        worker_thread(
+                            ctrl_rx,
+                            board_desc,
+                            fitness_function,
+                            queue,
+                            stats,
+                            config,
+                            deadline,
+                            rng,
+                        )
+                    }),
+                }
+            })
+            .collect();
+
+        if paralelism == 0 {
+            queue.finish();
+        }
+
+        Ok(Self {
+            board_desc,
+            queue,
+            workers: Mutex::new(workers),
+            stats,
+        })
+    }
+
+    pub fn get_new_room(&self, entry_direction: Direction) -> AutoGenOutput {
+        // Fallback boards take slots without emptying the buffer, so it can
+        // outlive the slots
+        if *self.queue.max_size.lock().unwrap() == 0 {
+            return AutoGenOutput::NoMoreBufferedBoards;
+        }
+
+        // Packed boards were picked offline, they go out in the pack's order
+        // before anything generated live
+        let packed = self.queue.packed.lock().unwrap().pop_front();
+
+        if let Some(candidate) = packed {
+            return self.serve(candidate, entry_direction);
+        }
 
-            *max_size -= 1;
+        let candidate = self.queue.candidates.lock().unwrap().pop();
 
-            if *max_size == 0 {
-                stop_search();
+        match candidate {
+            None => {
+                if *self.queue.state.lock().unwrap() == GeneratorState::Finished {
+                    AutoGenOutput::NoMoreBufferedBoards
+                } else {
+                    AutoGenOutput::NotReady
+                }
             }
 
-            candidate.board.print(
-                candidate.analysis.routes[0][0]
-                    .solution
-                    .iter()
-                    .map(|e| e.1)
-                    .collect(),
-            );
-            let mut board = candidate.board;
-            while board.gates[0].inwards_direction != entry_direction {
-                board = board.rotate_left();
+            Some(candidate) => {
+                self.queue.persist_or_log();
+                self.serve(candidate, entry_direction)
+            }
+        }
+    }
+
+    pub fn get_new_room_within(
+        &self,
+        entry_direction: Direction,
+        timeout: Duration,
+    ) -> BestEffortOutput {
+        let deadline = Instant::now() + timeout;
+
+        {
+            let mut candidates = self.queue.candidates.lock().unwrap();
+
+            while candidates.is_empty()
+                && self.queue.packed.lock().unwrap().is_empty()
+                && *self.queue.state.lock().unwrap() != GeneratorState::Finished
+                && Instant::now() < deadline
+            {
+                candidates = self
+                    .queue
+                    .ready
+                    .wait_timeout(
+                        candidates,
+                        deadline.saturating_duration_since(Instant::now()),
+                    )
+                    .unwrap()
+                    .0;
             }
+        }
+
+        if let AutoGenOutput::Ok(board) = self.get_new_room(entry_direction) {
+            return BestEffortOutput::Buffered(board);
+        }
 
-            let board = asthetic_cleanup(board, &candidate.analysis, 0);
+        if *self.queue.max_size.lock().unwrap() == 0 {
+            return BestEffortOutput::NoMoreBufferedBoards;
+        }
 
-            let board_desc = G_BOARD_DESC.lock().unwrap();
+        let best_rejected = self.queue.best_rejected.lock().unwrap().take();
 
-            AutoGenOutput::Ok(DartBoard::new(
-                board,
-                Some((candidate.analysis, board_desc.clone().unwrap().game_mode)),
-            ))
+        if let Some(candidate) = best_rejected {
+            return match self.serve(candidate, entry_direction) {
+                AutoGenOutput::Ok(board) => BestEffortOutput::BelowThreshold(board),
+                _ => BestEffortOutput::NoMoreBufferedBoards,
+            };
         }
+
+        // Without the optional constraints almost any solvable room will do
+        let mut relaxed_desc = self.board_desc.clone();
+        relaxed_desc.min_optimal_moves = 0;
+        relaxed_desc.max_optimal_moves = relaxed_desc.max_solution_depth;
+        relaxed_desc.min_difficulty = 0;
+        relaxed_desc.max_difficulty = 100;
+
+        for _ in 0..FALLBACK_ATTEMPTS {
+            if Instant::now() >= deadline + FALLBACK_ALLOWANCE {
+                break;
+            }
+
+            if let Ok(board) = generate_seeded_room(&relaxed_desc, rand::random(), entry_direction)
+            {
+                return match self.take_slot() {
+                    true => BestEffortOutput::Fallback(board),
+                    false => BestEffortOutput::NoMoreBufferedBoards,
+                };
+            }
+        }
+
+        BestEffortOutput::NotReady
     }
-}
 
-pub fn start_search(board_desc: BoardDescription, max_buffered_boards: isize) {
-    {
-        *(G_RESULT_MAX_SIZE.lock().unwrap()) = max_buffered_boards as usize;
+    fn serve(&self, candidate: Candidate, entry_direction: Direction) -> AutoGenOutput {
+        if !self.take_slot() {
+            return AutoGenOutput::NoMoreBufferedBoards;
+        }
+
+        AutoGenOutput::Ok(build_room(
+            candidate.board,
+            candidate.analysis,
+            candidate.difficulty,
+            &self.board_desc,
+            candidate.seed,
+            entry_direction,
+        ))
     }
-    {
-        *(G_BOARD_DESC.lock().unwrap()) = Some(board_desc.clone());
+
+    // Callers racing for the last slot both got past their own check, only
+    // one of them gets it
+    fn take_slot(&self) -> bool {
+        let mut max_size = self.queue.max_size.lock().unwrap();
+
+        let Some(left) = max_size.checked_sub(1) else {
+            return false;
+        };
+        *max_size = left;
+        self.queue.stagnant_iterations.store(0, Ordering::Relaxed);
+
+        if left == 0 {
+            self.kill();
+        }
+
+        true
     }
 
-    let mut g_worker_queue = G_WORKER.lock().unwrap();
+    // Workers that ran out of budget have already hung up, so failed sends are
+    // expected
+    fn send(&self, msg: impl Fn() -> CtrlMsg) {
+        self.workers.lock().unwrap().iter().for_each(|e| {
+            let _ = e.crtl_channel.send(msg());
+        });
+    }
 
-    if let None = *g_worker_queue {
-        let paralelism = available_parallelism()
-            .expect("couldnt get available parallelism")
-            .get()
-            / 2;
-        // let paralelism = 1;
+    // Workers are only told to finish, joining them here could deadlock with
+    // a worker waiting on the result queue
+    fn kill(&self) {
+        if self.queue.finish() {
+            self.send(|| CtrlMsg::Kill);
+        }
+    }
 
-        *g_worker_queue = Some(
-            (0..paralelism)
-                .map(|_| {
-                    let (ctrl_tx, ctrl_rx) = mpsc::channel();
+    pub fn halt(&self, millis: usize) {
+        if *self.queue.state.lock().unwrap() != GeneratorState::Finished {
+            self.send(|| CtrlMsg::Halt(millis));
+        }
+    }
 
-                    let board_desc = board_desc.clone();
+    pub fn pause(&self) {
+        let mut state = self.queue.state.lock().unwrap();
 
-                    Worker {
-                        crtl_channel: ctrl_tx,
-                        join: spawn(move || worker_thread(ctrl_rx, board_desc.clone())),
-                    }
-                })
+        if *state == GeneratorState::Running {
+            *state = GeneratorState::Paused;
+            self.send(|| CtrlMsg::Pause);
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.queue.state.lock().unwrap();
+
+        if *state == GeneratorState::Paused {
+            *state = GeneratorState::Running;
+            self.send(|| CtrlMsg::Resume);
+        }
+    }
+
+    // Every worker is joined even if one of them panicked
+    pub fn shutdown(&self) -> Result<(), String> {
+        self.kill();
+
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap());
+        let panicked = workers
+            .into_iter()
+            .enumerate()
+            .filter_map(|(worker_id, worker)| {
+                worker.join.join().is_err().then(|| worker_id.to_string())
+            })
+            .collect::<Vec<_>>();
+
+        match panicked.is_empty() {
+            true => Ok(()),
+            false => Err(format!("workers {} panicked", panicked.join(", "))),
+        }
+    }
+
+    pub fn persist(&self) -> Result<(), String> {
+        self.queue.persist()
+    }
+
+    // Hands the buffered boards out as generated, best first, to tools that
+    // store them rather than play them
+    pub fn drain(&self) -> Vec<PackedBoard> {
+        let mut candidates = self.queue.candidates.lock().unwrap();
+        let mut ret = vec![];
+
+        while let Some(candidate) = candidates.pop() {
+            ret.push(PackedBoard {
+                board: candidate.board,
+                analysis: candidate.analysis,
+                fitness: candidate.fitness,
+                seed: candidate.seed,
+            });
+        }
+
+        ret
+    }
+
+    pub fn subscribe(&self, sink: StreamSink<GeneratorEvent>) {
+        if *self.queue.state.lock().unwrap() == GeneratorState::Finished {
+            let _ = sink.add(GeneratorEvent::Finished);
+        } else {
+            self.queue.sinks.lock().unwrap().push(sink);
+        }
+    }
+
+    pub fn status(&self) -> GeneratorStatus {
+        let sum = |count: fn(&WorkerStats) -> &AtomicUsize| {
+            self.stats
+                .iter()
+                .map(|e| count(e).load(Ordering::Relaxed))
+                .sum::<usize>()
+        };
+
+        let candidates_tried = sum(|e| &e.candidates);
+        let solvable = sum(|e| &e.solvable);
+
+        let candidates = self.queue.candidates.lock().unwrap();
+
+        GeneratorStatus {
+            state: self.queue.state.lock().unwrap().clone(),
+            iterations_per_worker: self
+                .stats
+                .iter()
+                .map(|e| e.iterations.load(Ordering::Relaxed) as isize)
                 .collect(),
-        );
+            candidates_tried: candidates_tried as isize,
+            solvable_rate: solvable as f64 / candidates_tried.max(1) as f64,
+            fitness_threshold: candidates.first().map_or(0., |e| e.fitness as f64),
+            buffered_boards: (candidates.len() + self.queue.packed.lock().unwrap().len()) as isize,
+            remaining_boards: *self.queue.max_size.lock().unwrap() as isize,
+        }
     }
 }
 
-pub fn stop_search() {
-    let mut g_worker_queue = G_WORKER.lock().unwrap();
+// Handles are dropped from the UI thread, so the workers are only told to stop
+// and left to finish their current candidate on their own. Only an explicit
+// shutdown waits for them.
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
 
-    if let Some(workers) = g_worker_queue.take() {
-        workers.iter().for_each(|e| {
-            e.crtl_channel
-                .send(CtrlMsg::Kill)
-                .expect("worker leak: Worker comunications failed on kill")
+// One file per description, named after a hash that is stable across runs
+fn buffer_path(dir: &str, board_desc: &BoardDescription) -> String {
+    let hash = board_desc
+        .as_list()
+        .iter()
+        .flat_map(|e| (*e as i64).to_le_bytes())
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
         });
+
+    format!("{}/boards_{hash:016x}.bin", dir.trim_end_matches('/'))
+}
+
+// Boards written by an older build may not be solved the same way anymore,
+// so every route is replayed before the board is trusted again
+fn restore(path: &str, board_desc: &BoardDescription) -> Result<Vec<Candidate>, String> {
+    if !std::path::Path::new(path).exists() {
+        return Ok(vec![]);
+    }
+
+    let (stored_desc, candidates): (BoardDescription, Vec<Candidate>) =
+        read_file(path, BUFFER_MAGIC, BUFFER_VERSION)?;
+
+    if stored_desc != *board_desc {
+        return Err(format!("{path} was written for another description"));
     }
+
+    Ok(candidates
+        .into_iter()
+        .filter(|candidate| candidate.analysis.check_still_applies(&candidate.board, 0))
+        .collect())
 }
 
-pub fn halt_search(millis: usize) {
-    let mut g_worker_queue = G_WORKER.lock().unwrap();
+fn build_room(
+    board: Board,
+    analysis: Analysis,
+    difficulty: Option<DifficultyReport>,
+    board_desc: &BoardDescription,
+    seed: u64,
+    entry_direction: Direction,
+) -> DartBoard {
+    let difficulty = difficulty.unwrap_or_else(|| DifficultyReport::new(&board, &analysis, 0, 1));
 
-    let worker_queue = g_worker_queue.take();
+    board.print(analysis.routes[0][0].solution.iter().map(|e| e.1).collect());
 
-    if let Some(ref workers) = worker_queue {
-        workers.iter().for_each(|e| {
-            e.crtl_channel
-                .send(CtrlMsg::Halt(millis))
-                .expect("worker leak: Worker comunications failed on kill")
-        });
+    let mut board = board;
+    while board.gates[0].inwards_direction != entry_direction {
+        board = board.rotate_left();
     }
 
-    *g_worker_queue = worker_queue;
+    let board = asthetic_cleanup(board, &analysis, 0);
+
+    DartBoard::new(
+        board,
+        Some((analysis, board_desc.game_mode.clone())),
+        Some(difficulty),
+        SolverLimits::from(board_desc),
+        seed,
+    )
+}
+
+pub fn generate_seeded_room(
+    board_desc: &BoardDescription,
+    seed: u64,
+    entry_direction: Direction,
+) -> Result<DartBoard, String> {
+    let limits = SolverLimits::from(board_desc);
+    let board = Board::new_random(board_desc, &mut StdRng::seed_from_u64(seed))?;
+    let analysis = analyze(&board, 0, 1, &limits)?;
+    let (board, analysis) = strip_unused_stops(board, analysis, &limits)?;
+
+    Ok(build_room(
+        board,
+        analysis,
+        None,
+        board_desc,
+        seed,
+        entry_direction,
+    ))
 }
 
-pub fn worker_thread(messenger: mpsc::Receiver<CtrlMsg>, board_desc: BoardDescription) {
+#[allow(clippy::too_many_arguments)]
+fn worker_thread(
+    messenger: mpsc::Receiver<CtrlMsg>,
+    board_desc: BoardDescription,
+    fitness_function: Box<dyn FitnessFunction>,
+    queue: Arc<ResultQueue>,
+    stats: Arc<WorkerStats>,
+    config: GeneratorConfig,
+    deadline: Option<Instant>,
+    mut seeder: StdRng,
+) {
+    let limits = SolverLimits::from(&board_desc);
+    let duty_cycle = config.duty_cycle.clamp(0.01, 1.);
     let mut fitness_filter = 0.;
     let mut iter = 0;
+    let mut work_started: Option<Instant> = None;
 
     loop {
         iter += 1;
-        match messenger.try_recv() {
-            Ok(CtrlMsg::Halt(time)) => {
-                thread::sleep(time::Duration::from_millis(time as u64));
-                continue;
-            }
-            Ok(CtrlMsg::Kill) => {
-                println!("reached {iter} iter, killed by ctrl msg");
+        stats.iterations.store(iter, Ordering::Relaxed);
 
-                return;
-            }
-            Err(_) => {}
+        // Resting in proportion to the last iteration keeps the worker busy
+        // for roughly duty_cycle of the time
+        let rest = work_started.map_or(Duration::ZERO, |started| {
+            started.elapsed().mul_f64(1. / duty_cycle - 1.)
+        });
+
+        let still_alive = match messenger.try_recv() {
+            Ok(CtrlMsg::Halt(time)) => idle(&messenger, Some(Duration::from_millis(time as u64))),
+            Ok(CtrlMsg::Pause) => idle(&messenger, None),
+            Ok(CtrlMsg::Resume) | Err(TryRecvError::Empty) => true,
+            Ok(CtrlMsg::Kill) | Err(TryRecvError::Disconnected) => false,
+        } && (rest.is_zero() || idle(&messenger, Some(rest)));
+
+        if !still_alive {
+            println!("reached {iter} iter, killed by ctrl msg");
+
+            break;
         }
 
-        if let Ok(board) = Board::new_random(&board_desc) {
-            if let Ok(analysis) = analyze(&board, 0, 1) {
-                let fitness = analysis.compute_fitness(&board.map, &board_desc);
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            println!("reached {iter} iter, out of time budget");
+
+            break;
+        }
+
+        if queue.is_stagnant(config.stagnation_limit) {
+            println!("reached {iter} iter, no new board for too long");
+
+            break;
+        }
+
+        work_started = Some(Instant::now());
+
+        let seed = seeder.random();
+
+        if let Ok(board) = Board::new_random(&board_desc, &mut StdRng::seed_from_u64(seed)) {
+            stats.candidates.fetch_add(1, Ordering::Relaxed);
+
+            if let Ok((board, analysis)) = analyze(&board, 0, 1, &limits)
+                .and_then(|analysis| strip_unused_stops(board, analysis, &limits))
+            {
+                stats.solvable.fetch_add(1, Ordering::Relaxed);
+
+                if !board_desc.allows_optimal_movement_count(analysis.optimal_movement_count) {
+                    continue;
+                }
+
+                if !analysis.picks_up_every_key(&board, 0) {
+                    continue;
+                }
+
+                // Rating is as expensive as solving, so only pay for it when asked to
+                let difficulty = if board_desc.restricts_difficulty() {
+                    let difficulty = DifficultyReport::new(&board, &analysis, 0, 1);
+
+                    if !board_desc.allows_difficulty(difficulty.difficulty) {
+                        continue;
+                    }
+
+                    Some(difficulty)
+                } else {
+                    None
+                };
+
+                let fitness = fitness_function.score(&analysis, &board.map);
 
                 if fitness > fitness_filter {
-                    fitness_filter = submit(Candidate {
+                    fitness_filter = queue.submit(Candidate {
+                        fitness,
+                        board,
+                        analysis,
+                        difficulty,
+                        seed,
+                    })
+                } else {
+                    queue.reject(Candidate {
                         fitness,
                         board,
                         analysis,
+                        difficulty,
+                        seed,
                     })
                 }
             }
         }
     }
+
+    // The last worker to stop finishes the pool on its own
+    if queue.live_workers.fetch_sub(1, Ordering::Relaxed) == 1 {
+        queue.finish();
+    }
+}
+
+// Blocks until resumed or until the halt runs out, returns false if the worker
+// got killed meanwhile
+fn idle(messenger: &mpsc::Receiver<CtrlMsg>, halt: Option<Duration>) -> bool {
+    let mut deadline = halt.map(|halt| Instant::now() + halt);
+
+    loop {
+        let msg = match deadline {
+            Some(deadline) => {
+                match messenger.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Err(RecvTimeoutError::Timeout) => return true,
+                    msg => msg.ok(),
+                }
+            }
+            None => messenger.recv().ok(),
+        };
+
+        match msg {
+            Some(CtrlMsg::Resume) => return true,
+            Some(CtrlMsg::Pause) => deadline = None,
+            Some(CtrlMsg::Halt(millis)) => {
+                deadline =
+                    deadline.map(|d| d.max(Instant::now() + Duration::from_millis(millis as u64)))
+            }
+            Some(CtrlMsg::Kill) | None => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> GeneratorConfig {
+        GeneratorConfig {
+            thread_count: Some(1),
+            stagnation_limit: Some(50),
+            ..GeneratorConfig::default()
+        }
+    }
+
+    fn wait_until_finished(pool: &WorkerPool) {
+        let started = Instant::now();
+
+        while pool.status().state != GeneratorState::Finished {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "pool never finished"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn seeds(boards: Vec<PackedBoard>) -> Vec<u64> {
+        boards.iter().map(|board| board.seed).collect()
+    }
+
+    #[test]
+    fn pools_run_side_by_side() {
+        let small = BoardDescription::default();
+        let large = BoardDescription {
+            area: 400,
+            ..BoardDescription::default()
+        };
+
+        let pools = [
+            WorkerPool::start(small.clone(), 3, Some(7), config()).unwrap(),
+            WorkerPool::start(large, 3, Some(7), config()).unwrap(),
+            WorkerPool::start(small, 3, Some(7), config()).unwrap(),
+        ];
+        pools.iter().for_each(wait_until_finished);
+
+        let [small_a, large, small_b] = pools.map(|pool| pool.drain());
+
+        assert_eq!(small_a.len(), 3);
+        assert_eq!(large.len(), 3);
+        // A pool only sees its own description and its own seed sequence
+        assert_eq!(seeds(small_a.clone()), seeds(small_b));
+        assert!(small_a
+            .iter()
+            .zip(&large)
+            .all(|(small, large)| small.board.map.0.len() < large.board.map.0.len()));
+    }
+
+    #[test]
+    fn serving_one_pool_leaves_the_other_alone() {
+        let a = WorkerPool::start(BoardDescription::default(), 1, Some(1), config()).unwrap();
+        let b = WorkerPool::start(BoardDescription::default(), 2, Some(2), config()).unwrap();
+        wait_until_finished(&a);
+        wait_until_finished(&b);
+
+        assert!(matches!(
+            a.get_new_room(Direction::North),
+            AutoGenOutput::Ok(_)
+        ));
+        assert!(matches!(
+            a.get_new_room(Direction::North),
+            AutoGenOutput::NoMoreBufferedBoards
+        ));
+
+        assert_eq!(b.status().buffered_boards, 2);
+        assert_eq!(b.status().remaining_boards, 2);
+    }
+
+    #[test]
+    fn pause_resume_and_shutdown() {
+        let config = GeneratorConfig {
+            stagnation_limit: None,
+            ..config()
+        };
+        let pool = WorkerPool::start(BoardDescription::default(), 3, Some(3), config).unwrap();
+
+        pool.pause();
+        assert_eq!(pool.status().state, GeneratorState::Paused);
+        pool.pause();
+        assert_eq!(pool.status().state, GeneratorState::Paused);
+
+        pool.resume();
+        assert_eq!(pool.status().state, GeneratorState::Running);
+
+        assert_eq!(pool.shutdown(), Ok(()));
+        assert_eq!(pool.status().state, GeneratorState::Finished);
+
+        // Nothing left to pause or resume once finished
+        pool.resume();
+        assert_eq!(pool.status().state, GeneratorState::Finished);
+        assert_eq!(pool.shutdown(), Ok(()));
+    }
+
+    #[test]
+    fn racing_takers_never_get_more_than_the_count() {
+        let config = GeneratorConfig {
+            stagnation_limit: None,
+            ..config()
+        };
+        let pool = WorkerPool::start(BoardDescription::default(), 2, Some(4), config).unwrap();
+        let started = Instant::now();
+
+        while pool.status().buffered_boards < 2 {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "buffer never filled"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let served = std::thread::scope(|scope| {
+            let takers = (0..8)
+                .map(|_| scope.spawn(|| pool.get_new_room(Direction::North)))
+                .collect::<Vec<_>>();
+
+            takers
+                .into_iter()
+                .map(|taker| taker.join())
+                .filter(|served| matches!(served, Ok(AutoGenOutput::Ok(_))))
+                .count()
+        });
+
+        assert_eq!(served, 2);
+        assert_eq!(pool.status().remaining_boards, 0);
+        assert!(!pool.take_slot());
+    }
+
+    // StreamSinks need a Dart port, so the events are checked through the
+    // status they are built from
+    #[test]
+    fn threshold_only_improves_and_finishes_once() {
+        let pool = WorkerPool::start(BoardDescription::default(), 3, Some(5), config()).unwrap();
+        let started = Instant::now();
+        let mut threshold = 0.;
+
+        loop {
+            let status = pool.status();
+
+            assert!(status.fitness_threshold >= threshold);
+            assert!(status.buffered_boards <= 3);
+            threshold = status.fitness_threshold;
+
+            if status.state == GeneratorState::Finished {
+                break;
+            }
+
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "pool never finished"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(threshold > 0.);
+        assert!(!pool.queue.finish());
+    }
+
+    #[test]
+    fn a_buffer_that_never_fills_is_left_to_the_time_budget() {
+        let unreachable = BoardDescription {
+            min_optimal_moves: 1000,
+            max_optimal_moves: 1000,
+            ..BoardDescription::default()
+        };
+        let config = GeneratorConfig {
+            thread_count: Some(2),
+            time_budget_millis: Some(200),
+            stagnation_limit: Some(1),
+            ..GeneratorConfig::default()
+        };
+        let pool = WorkerPool::start(unreachable, 1, Some(6), config).unwrap();
+        wait_until_finished(&pool);
+
+        let status = pool.status();
+        assert_eq!(status.buffered_boards, 0);
+        assert_eq!(status.iterations_per_worker.len(), 2);
+        assert!(status.iterations_per_worker.iter().all(|&e| e > 1));
+    }
+
+    #[test]
+    fn a_full_buffer_stagnates_until_a_board_is_taken() {
+        let config = GeneratorConfig {
+            stagnation_limit: Some(5),
+            ..config()
+        };
+        let pool = WorkerPool::start(BoardDescription::default(), 2, Some(8), config).unwrap();
+        pool.pause();
+
+        pool.queue.candidates.lock().unwrap().clear();
+        assert!(!pool.queue.is_stagnant(Some(1)));
+
+        let started = Instant::now();
+        while pool.queue.candidates.lock().unwrap().len() < 2 {
+            pool.resume();
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "buffer never filled"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        wait_until_finished(&pool);
+
+        assert!(pool.queue.is_stagnant(Some(1)));
+        assert!(pool.take_slot());
+        assert_eq!(pool.queue.stagnant_iterations.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn best_effort_serves_buffered_boards_first() {
+        let pool = WorkerPool::start(BoardDescription::default(), 1, Some(9), config()).unwrap();
+        wait_until_finished(&pool);
+
+        assert!(matches!(
+            pool.get_new_room_within(Direction::North, Duration::from_millis(10)),
+            BestEffortOutput::Buffered(_)
+        ));
+        assert!(matches!(
+            pool.get_new_room_within(Direction::North, Duration::from_millis(10)),
+            BestEffortOutput::NoMoreBufferedBoards
+        ));
+    }
+
+    #[test]
+    fn best_effort_falls_back_when_nothing_passes() {
+        let unreachable = BoardDescription {
+            min_optimal_moves: 1000,
+            max_optimal_moves: 1000,
+            ..BoardDescription::default()
+        };
+        let config = GeneratorConfig {
+            stagnation_limit: None,
+            ..config()
+        };
+        let pool = WorkerPool::start(unreachable, 2, Some(10), config).unwrap();
+
+        let started = Instant::now();
+        let served = (0..3)
+            .map(|_| pool.get_new_room_within(Direction::North, Duration::from_millis(50)))
+            .collect::<Vec<_>>();
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(served[..2].iter().all(|output| matches!(
+            output,
+            BestEffortOutput::BelowThreshold(_) | BestEffortOutput::Fallback(_)
+        )));
+        assert!(matches!(served[2], BestEffortOutput::NoMoreBufferedBoards));
+        assert_eq!(pool.status().state, GeneratorState::Finished);
+    }
 }